@@ -0,0 +1,12 @@
+-- Add migration script here
+-- migrations/{timestamp}_add_status_to_subscriptions.sql
+-- Double opt-in: a subscriber is `pending_confirmation` until they click the confirmation link.
+ALTER TABLE subscriptions ADD COLUMN status TEXT NULL;
+
+-- Rows created before double opt-in existed are considered confirmed (we have been mailing them already).
+UPDATE subscriptions SET status = 'confirmed' WHERE status IS NULL;
+
+ALTER TABLE subscriptions ALTER COLUMN status SET NOT NULL;
+ALTER TABLE subscriptions
+    ADD CONSTRAINT subscriptions_status_check
+    CHECK (status IN ('pending_confirmation', 'confirmed'));
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- migrations/{timestamp}_create_subscription_tokens_table.sql -- Create Subscription Tokens Table
+CREATE TABLE subscription_tokens(
+    subscription_token TEXT NOT NULL,
+    PRIMARY KEY (subscription_token),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id)
+);
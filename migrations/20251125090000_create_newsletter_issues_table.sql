@@ -0,0 +1,10 @@
+-- Add migration script here
+-- migrations/{timestamp}_create_newsletter_issues_table.sql -- Create Newsletter Issues Table
+CREATE TABLE newsletter_issues (
+    newsletter_issue_id uuid NOT NULL,
+    PRIMARY KEY (newsletter_issue_id),
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    published_at timestamptz NOT NULL
+);
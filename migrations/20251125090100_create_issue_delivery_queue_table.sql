@@ -0,0 +1,22 @@
+-- Add migration script here
+-- migrations/{timestamp}_create_issue_delivery_queue_table.sql -- Create Issue Delivery Queue Table
+-- One row per (issue, recipient): a task for the delivery workers.
+-- A row is deleted once its email has been sent; it stays around, as `dead_letter`,
+-- once we've given up on it.
+CREATE TABLE issue_delivery_queue (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email),
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'dead_letter')),
+    n_retries INT NOT NULL DEFAULT 0,
+    -- Exponential backoff: a failed task is not picked up again before this instant
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    last_error TEXT NULL
+);
+
+-- Workers poll for "pending tasks that are due": keep that lookup cheap.
+CREATE INDEX issue_delivery_queue_pending_idx
+    ON issue_delivery_queue (execute_after)
+    WHERE status = 'pending';
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- migrations/{timestamp}_create_users_table.sql -- Create Users Table
+-- `password_hash` is a PHC string: algorithm, parameters and salt travel with the hash itself.
+CREATE TABLE users(
+    user_id uuid PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL
+);
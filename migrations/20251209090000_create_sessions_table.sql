@@ -0,0 +1,8 @@
+-- Add migration script here
+-- migrations/{timestamp}_create_sessions_table.sql -- Create Sessions Table
+-- Server-side session state: the cookie only carries the (signed) `session_key`.
+CREATE TABLE sessions(
+    session_key TEXT PRIMARY KEY,
+    state JSONB NOT NULL,
+    expires_at timestamptz NOT NULL
+);
@@ -0,0 +1,23 @@
+-- Add migration script here
+-- migrations/{timestamp}_create_idempotency_table.sql -- Create Idempotency Table
+-- The response to the first request carrying a given (user, idempotency key):
+-- retries and double-clicks get it back, instead of running the request again.
+CREATE TYPE header_pair AS (
+    name TEXT,
+    value BYTEA
+);
+
+CREATE TABLE idempotency (
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    idempotency_key TEXT NOT NULL,
+    -- NULL while the first request is being processed: the row is inserted upfront,
+    -- and its (row-level) lock is what makes concurrent duplicates wait.
+    response_status_code SMALLINT NULL,
+    response_headers header_pair[] NULL,
+    response_body BYTEA NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, idempotency_key)
+);
+
+-- The cleanup task deletes by age
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
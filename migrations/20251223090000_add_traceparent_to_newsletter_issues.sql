@@ -0,0 +1,5 @@
+-- Add migration script here
+-- migrations/{timestamp}_add_traceparent_to_newsletter_issues.sql -- Add Trace Context to Newsletter Issues
+-- The W3C `traceparent` of the request that published the issue, if it was traced:
+-- the delivery workers send its emails as part of the same trace.
+ALTER TABLE newsletter_issues ADD COLUMN traceparent TEXT NULL;
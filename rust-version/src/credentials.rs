@@ -0,0 +1,218 @@
+//! src/credentials.rs
+//! Who is making the request, and can they prove it?
+
+use actix_web::http::header::HeaderMap;
+use anyhow::Context;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use base64::Engine;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::PasswordHashingSettings;
+use crate::telemetry::spawn_blocking_with_tracing;
+
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    /// Unknown username, wrong password, malformed header...: the caller's fault.
+    #[error("Invalid credentials: {0}")]
+    InvalidCredentials(String),
+    /// The database is down, the stored hash is corrupted...: ours.
+    #[error("Authentication failed")]
+    Unexpected(#[from] anyhow::Error),
+}
+
+/// Extracts the credentials from an `Authorization: Basic <base64(username:password)>` header.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, AuthError> {
+    let invalid = |reason: &str| AuthError::InvalidCredentials(reason.to_string());
+
+    let header_value = headers
+        .get("Authorization")
+        .ok_or_else(|| invalid("The 'Authorization' header was missing"))?
+        .to_str()
+        .map_err(|_| invalid("The 'Authorization' header was not a valid UTF8 string"))?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .ok_or_else(|| invalid("The authorization scheme was not 'Basic'"))?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .map_err(|_| invalid("Failed to base64-decode 'Basic' credentials"))?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .map_err(|_| invalid("The decoded credential string is not valid UTF8"))?;
+
+    // Split into two segments, using ':' as delimiter
+    // (the password may well contain ':' itself, the username may not).
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or_else(|| invalid("A username and a password must be provided in 'Basic' auth"))?;
+
+    Ok(Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+/// Returns the `user_id` of the user the credentials belong to.
+///
+/// A stored hash computed with other parameters than `hashing`'s is replaced,
+/// now that we know the password, by one computed with the current ones.
+#[tracing::instrument(
+    name = "Validate credentials",
+    skip(credentials, hashing, db_conn_pool)
+)]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    hashing: &PasswordHashingSettings,
+    db_conn_pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    // TIMING ATTACKS: if unknown usernames were rejected straight away, they would be
+    // answered much faster than wrong passwords (hashing is SLOW, on purpose),
+    // and an attacker could enumerate our users just by timing our responses.
+    // So we ALWAYS verify a hash: the real one, or a dummy one with the same parameters.
+    let mut user_id = None;
+    let mut expected_password_hash = dummy_password_hash(hashing);
+
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, db_conn_pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    // Hashing is CPU-bound and takes tens of milliseconds: running it on the async
+    // runtime's threads would stall every other request scheduled on them.
+    let current = hashing.clone();
+    let (password, needs_rehash) = spawn_blocking_with_tracing(move || {
+        verify_password_hash(&expected_password_hash, &credentials.password)?;
+        let needs_rehash = needs_rehash(&expected_password_hash, &current);
+        Ok::<_, AuthError>((credentials.password, needs_rehash))
+    })
+    .await
+    .context("Failed to spawn blocking task")??;
+
+    // Only `Some` if a user was found AND the password matched
+    let user_id =
+        user_id.ok_or_else(|| AuthError::InvalidCredentials("Unknown username".into()))?;
+
+    if needs_rehash {
+        // Best effort: the user proved who they are, failing to upgrade their hash
+        // is no reason to turn them away. We'll try again next time.
+        if let Err(e) = change_password(user_id, password, hashing, db_conn_pool).await {
+            tracing::warn!("Failed to upgrade the password hash: {:?}", e);
+        }
+    }
+    Ok(user_id)
+}
+
+/// Stores a hash of `password` (with the current parameters) as `user_id`'s new password.
+#[tracing::instrument(name = "Change password", skip(password, hashing, db_conn_pool))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: String,
+    hashing: &PasswordHashingSettings,
+    db_conn_pool: &PgPool,
+) -> Result<(), AuthError> {
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(&password, &hashing))
+            .await
+            .context("Failed to spawn blocking task")??;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2
+        "#,
+        password_hash,
+        user_id,
+    )
+    .execute(db_conn_pool)
+    .await
+    .context("Failed to store the password hash")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Verify password hash", skip_all)]
+fn verify_password_hash(
+    expected_password_hash: &str,
+    password_candidate: &str,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash)
+        .context("Failed to parse hash in PHC string format")?;
+
+    // The algorithm, its parameters and the salt are all read from the PHC string itself.
+    Argon2::default()
+        .verify_password(password_candidate.as_bytes(), &expected_password_hash)
+        .map_err(|_| AuthError::InvalidCredentials("Invalid password".into()))
+}
+
+/// Was this (valid) hash computed with anything else than Argon2id and the current parameters?
+fn needs_rehash(password_hash: &str, hashing: &PasswordHashingSettings) -> bool {
+    let Ok(password_hash) = PasswordHash::new(password_hash) else {
+        return true;
+    };
+    let Ok(stored) = Params::try_from(&password_hash) else {
+        return true;
+    };
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+        || stored.m_cost() != hashing.memory_kib
+        || stored.t_cost() != hashing.iterations
+        || stored.p_cost() != hashing.parallelism
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, db_conn_pool))]
+async fn get_stored_credentials(
+    username: &str,
+    db_conn_pool: &PgPool,
+) -> Result<Option<(Uuid, String)>, AuthError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash
+        FROM users
+        WHERE username = $1
+        "#,
+        username,
+    )
+    .fetch_optional(db_conn_pool)
+    .await
+    .context("Failed to retrieve stored credentials")?
+    .map(|row| (row.user_id, row.password_hash));
+    Ok(row)
+}
+
+/// Argon2id, with the cost parameters from the configuration.
+pub fn argon2_hasher(hashing: &PasswordHashingSettings) -> Result<Argon2<'static>, AuthError> {
+    let params = hashing.params().context("Invalid Argon2 parameters")?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Hashes a password, with a fresh random salt, into a PHC string.
+pub fn compute_password_hash(
+    password: &str,
+    hashing: &PasswordHashingSettings,
+) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = argon2_hasher(hashing)?
+        .hash_password(password.as_bytes(), &salt)
+        .context("Failed to hash password")?
+        .to_string();
+    Ok(password_hash)
+}
+
+// Same algorithm and parameters as real hashes: verifying against it costs just as much.
+// NOTE: the cost of a verification only depends on the parameters, not on the salt
+// or the (never matching) hash, hence a constant salt and output.
+fn dummy_password_hash(hashing: &PasswordHashingSettings) -> String {
+    format!(
+        "$argon2id$v=19$m={},t={},p={}$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
+        hashing.memory_kib, hashing.iterations, hashing.parallelism
+    )
+}
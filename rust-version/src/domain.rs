@@ -0,0 +1,17 @@
+//! src/domain.rs
+//! Domain types: values that can only exist once they have been validated.
+//!
+//! PARSE, DON'T VALIDATE: instead of checking raw `String`s here and there,
+//! we parse them ONCE, at the edge of the system, into types whose mere existence
+//! is a proof that the invariants hold.
+//! (SCALA: like opaque types / newtypes with a smart constructor returning Either)
+
+mod new_subscriber;
+mod subscriber_email;
+mod subscriber_name;
+mod subscription_token;
+
+pub use new_subscriber::NewSubscriber;
+pub use subscriber_email::SubscriberEmail;
+pub use subscriber_name::SubscriberName;
+pub use subscription_token::SubscriptionToken;
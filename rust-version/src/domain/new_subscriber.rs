@@ -0,0 +1,10 @@
+//! src/domain/new_subscriber.rs
+
+use crate::domain::{SubscriberEmail, SubscriberName};
+
+// SCALA EQUIVALENT: final case class NewSubscriber(email: SubscriberEmail, name: SubscriberName)
+// Its fields can be public: both of them are already validated types.
+pub struct NewSubscriber {
+    pub email: SubscriberEmail,
+    pub name: SubscriberName,
+}
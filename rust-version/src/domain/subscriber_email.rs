@@ -0,0 +1,28 @@
+//! src/domain/subscriber_email.rs
+
+use validator::ValidateEmail;
+
+#[derive(Debug)]
+pub struct SubscriberEmail(String);
+
+impl SubscriberEmail {
+    /// Returns an instance of `SubscriberEmail` if the input is a syntactically
+    /// valid email address (as per the HTML5 spec, a pragmatic subset of RFC 5322),
+    /// an error message otherwise.
+    pub fn parse(s: String) -> Result<SubscriberEmail, String> {
+        // NOTE: we are NOT rolling our own email validation (a notoriously hairy business),
+        // we delegate it to the `validator` crate.
+        if s.validate_email() {
+            Ok(Self(s))
+        } else {
+            // NOTE: the input stays out of the message: it is PII, and error messages are logged.
+            Err("Not a valid subscriber email.".to_string())
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberEmail {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
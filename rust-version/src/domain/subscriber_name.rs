@@ -0,0 +1,47 @@
+//! src/domain/subscriber_name.rs
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Characters that have no business in a name and are commonly used
+/// in injection attacks (HTML, SQL, shell...).
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+
+/// Upper bound on the length of a name, counted in graphemes.
+const MAX_GRAPHEMES: usize = 256;
+
+// A tuple struct with a single, PRIVATE, field.
+// The only way to get a `SubscriberName` from outside this module is `SubscriberName::parse`.
+// SCALA EQUIVALENT: final case class SubscriberName private (value: String)
+#[derive(Debug)]
+pub struct SubscriberName(String);
+
+impl SubscriberName {
+    /// Returns an instance of `SubscriberName` if the input satisfies all
+    /// our validation constraints on subscriber names, an error message otherwise.
+    pub fn parse(s: String) -> Result<SubscriberName, String> {
+        // `.trim()` returns a view over the input `s` without trailing whitespace-like characters.
+        let is_empty_or_whitespace = s.trim().is_empty();
+
+        // A grapheme is defined by the Unicode standard as a "user-perceived" character:
+        // `å` is a single grapheme, but it is composed of two characters (`a` and `̊`).
+        // `.graphemes(true)` uses the recommended "extended" grapheme definition.
+        let is_too_long = s.graphemes(true).count() > MAX_GRAPHEMES;
+
+        let contains_forbidden_characters = s.chars().any(|g| FORBIDDEN_CHARACTERS.contains(&g));
+
+        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
+            // Not echoed back: see `SubscriberEmail::parse`
+            Err("Not a valid subscriber name.".to_string())
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+// Read-only access to the inner value: callers can look, but not mutate
+// (and therefore cannot break the invariants established by `parse`).
+impl AsRef<str> for SubscriberName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
@@ -0,0 +1,47 @@
+//! src/domain/subscription_token.rs
+
+use rand::distributions::Alphanumeric;
+use rand::{Rng, thread_rng};
+
+/// Number of alphanumeric characters in a token.
+/// 62^25 possible values: guessing one is not a realistic attack.
+const TOKEN_LENGTH: usize = 25;
+
+#[derive(Debug)]
+pub struct SubscriptionToken(String);
+
+impl SubscriptionToken {
+    /// Generates a new, random, case-sensitive alphanumeric token.
+    pub fn generate() -> SubscriptionToken {
+        // `thread_rng` is a cryptographically secure PRNG, lazily seeded by the OS.
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(TOKEN_LENGTH)
+            .collect();
+        Self(token)
+    }
+
+    /// Returns an instance of `SubscriptionToken` if the input has the shape
+    /// of a token we could have issued, an error message otherwise.
+    // NOTE: it says nothing about whether the token was ACTUALLY issued:
+    // that's for the database to tell.
+    pub fn parse(s: String) -> Result<SubscriptionToken, String> {
+        let has_expected_length = s.chars().count() == TOKEN_LENGTH;
+        let is_alphanumeric = s.chars().all(|c| c.is_ascii_alphanumeric());
+
+        if has_expected_length && is_alphanumeric {
+            Ok(Self(s))
+        } else {
+            // Not echoed back: see `SubscriberEmail::parse`. A token is as good as a password
+            // for the subscription it belongs to.
+            Err("Not a valid subscription token.".to_string())
+        }
+    }
+}
+
+impl AsRef<str> for SubscriptionToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
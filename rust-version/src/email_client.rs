@@ -0,0 +1,91 @@
+//! src/email_client.rs
+//! Sending emails, through a PLUGGABLE transport:
+//! - `HttpTransport`: a (Postmark-style) transactional-email REST API
+//! - `SmtpTransport`: a plain old SMTP relay
+//!
+//! The rest of the application only ever sees `EmailClient`,
+//! and doesn't care which backend was picked in the configuration.
+
+mod http;
+mod smtp;
+
+pub use http::HttpTransport;
+pub use smtp::{SmtpAuthMechanism, SmtpTls, SmtpTransport};
+
+use crate::domain::SubscriberEmail;
+
+/// Everything a transport needs to know to deliver a single email.
+// Borrowing (`&'a str`) rather than owning: handing it to a transport should not
+// require cloning the (potentially large) email bodies.
+pub struct EmailMessage<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+}
+
+/// A way of getting an email from us to the recipient's inbox.
+// `#[async_trait]` boxes the returned future, which is what makes the trait usable
+// as a trait object (`Box<dyn EmailTransport>`).
+// SCALA EQUIVALENT: trait EmailTransport[F[_]] { def send(email: EmailMessage): F[Unit] }
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &EmailMessage<'_>) -> Result<(), EmailError>;
+
+    /// Checks that the backend can be reached, without sending anything.
+    async fn ping(&self) -> Result<(), EmailError>;
+}
+
+// `#[from]` is what makes `?` work: the error is converted on the way out.
+#[derive(thiserror::Error, Debug)]
+pub enum EmailError {
+    /// The email API could not be reached, or answered with a 4xx/5xx.
+    #[error("Failed to send the email through the HTTP API")]
+    Http(#[from] reqwest::Error),
+    /// The SMTP relay could not be reached, or rejected the message.
+    #[error("Failed to send the email through the SMTP relay")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    /// We could not even build a valid email out of the inputs.
+    #[error("Invalid email message: {0}")]
+    InvalidMessage(String),
+    /// A `ping` went unanswered.
+    #[error("The email backend is unreachable: {0}")]
+    Unreachable(String),
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self {
+            sender,
+            transport: Box::new(transport),
+        }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        let email = EmailMessage {
+            from: &self.sender,
+            to: recipient,
+            subject,
+            html_content,
+            text_content,
+        };
+        self.transport.send(&email).await
+    }
+
+    /// Is the backend reachable? (For the readiness probe.)
+    pub async fn ping(&self) -> Result<(), EmailError> {
+        self.transport.ping().await
+    }
+}
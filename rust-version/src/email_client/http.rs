@@ -0,0 +1,88 @@
+//! src/email_client/http.rs
+
+use std::time::Duration;
+
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::email_client::{EmailError, EmailMessage, EmailTransport};
+use crate::telemetry::current_traceparent;
+
+pub struct HttpTransport {
+    // `reqwest::Client` holds a CONNECTION POOL under the hood:
+    // we build it once and reuse it for every request, instead of paying for
+    // a new TCP (+ TLS) handshake each time we send an email.
+    http_client: Client,
+    base_url: String,
+    authorization_token: SecretString,
+}
+
+// The JSON payload expected by the email API.
+// SCALA EQUIVALENT: case class SendEmailRequest(...) derives Encoder
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+}
+
+impl HttpTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: SecretString,
+        timeout: Duration,
+    ) -> Result<Self, EmailError> {
+        // Without a timeout, a hanging email API would hang our request handlers with it.
+        // NOTE: building fails if no TLS backend can be initialised: a configuration
+        // problem, reported as such rather than a panic.
+        let http_client = Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            http_client,
+            base_url,
+            authorization_token,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for HttpTransport {
+    async fn send(&self, email: &EmailMessage<'_>) -> Result<(), EmailError> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendEmailRequest {
+            from: email.from.as_ref(),
+            to: email.to.as_ref(),
+            subject: email.subject,
+            html_body: email.html_content,
+            text_body: email.text_content,
+        };
+        let mut request = self.http_client.post(&url).header(
+            "X-Postmark-Server-Token",
+            self.authorization_token.expose_secret(),
+        );
+        // W3C Trace Context: the email API's spans (if it traces) join ours
+        if let Some(traceparent) = current_traceparent() {
+            request = request.header("traceparent", traceparent);
+        }
+        request
+            .json(&request_body)
+            .send()
+            .await?
+            // A 4xx/5xx is NOT a transport error for reqwest: we turn it into one explicitly.
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // There is no "ping" endpoint in the API: any HTTP answer, even a 404,
+    // means the server is up and reachable.
+    async fn ping(&self) -> Result<(), EmailError> {
+        self.http_client
+            .head(&self.base_url)
+            .send()
+            .await
+            .map_err(|e| EmailError::Unreachable(e.to_string()))?;
+        Ok(())
+    }
+}
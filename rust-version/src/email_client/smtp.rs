@@ -0,0 +1,115 @@
+//! src/email_client/smtp.rs
+
+use std::time::Duration;
+
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::PoolConfig;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::email_client::{EmailError, EmailMessage, EmailTransport};
+
+/// How (and whether) the connection to the relay gets encrypted.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plaintext all the way. Only sensible for a relay on localhost (or in tests).
+    None,
+    /// Connect in plaintext (usually on port 587), then upgrade with `STARTTLS`.
+    StartTls,
+    /// TLS from the very first byte (usually on port 465).
+    Implicit,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpAuthMechanism {
+    Plain,
+    Login,
+}
+
+impl From<SmtpAuthMechanism> for Mechanism {
+    fn from(mechanism: SmtpAuthMechanism) -> Self {
+        match mechanism {
+            SmtpAuthMechanism::Plain => Mechanism::Plain,
+            SmtpAuthMechanism::Login => Mechanism::Login,
+        }
+    }
+}
+
+pub struct SmtpTransport {
+    // Like `reqwest::Client`, lettre's transport keeps a POOL of open connections:
+    // consecutive emails reuse an authenticated session instead of redoing
+    // the TCP + TLS + EHLO + AUTH dance every single time.
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, SecretString)>,
+        auth_mechanism: SmtpAuthMechanism,
+        pool_max_size: u32,
+        timeout: Duration,
+    ) -> Result<Self, EmailError> {
+        let builder = match tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+        let mut builder = builder
+            .port(port)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(pool_max_size));
+        if let Some((username, password)) = credentials {
+            builder = builder
+                .credentials(Credentials::new(
+                    username,
+                    password.expose_secret().to_string(),
+                ))
+                .authentication(vec![auth_mechanism.into()]);
+        }
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+fn mailbox(email: &crate::domain::SubscriberEmail) -> Result<Mailbox, EmailError> {
+    email
+        .as_ref()
+        .parse()
+        .map_err(|e| EmailError::InvalidMessage(format!("{}", e)))
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &EmailMessage<'_>) -> Result<(), EmailError> {
+        let message = Message::builder()
+            .from(mailbox(email.from)?)
+            .to(mailbox(email.to)?)
+            .subject(email.subject)
+            // Both versions in a single email: clients render the best one they support.
+            .multipart(MultiPart::alternative_plain_html(
+                email.text_content.to_string(),
+                email.html_content.to_string(),
+            ))
+            .map_err(|e| EmailError::InvalidMessage(format!("{}", e)))?;
+        self.mailer.send(message).await?;
+        Ok(())
+    }
+
+    // Connects (and authenticates), then says `NOOP`.
+    async fn ping(&self) -> Result<(), EmailError> {
+        match self.mailer.test_connection().await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(EmailError::Unreachable(
+                "The SMTP relay did not acknowledge NOOP".into(),
+            )),
+            Err(e) => Err(EmailError::Unreachable(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! src/error.rs
+//! What every route's error type has in common: how it is printed, and how it is logged.
+//!
+//! Each route has its OWN error enum (e.g. `SubscribeError`), implementing actix's
+//! `ResponseError`: the variant decides the status code (and the body, if any) the caller
+//! gets. The full story (the source chain) goes to the logs, exactly once, through
+//! `log_failed_requests`: handlers return errors, they don't log them.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+
+/// `Debug` for error types: the error itself, then every error in its source chain.
+///
+/// # Implementation Notes
+///
+/// `#[derive(Debug)]` would print the variant's fields instead, which tells us WHAT
+/// failed but seldom WHY: the root cause is usually a few `source()`s away.
+// SCALA: like printing a `Throwable` with all its `getCause`s
+pub fn error_chain_fmt(
+    e: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{}\n", e)?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+/// Middleware: logs the error behind a failed request, source chain included.
+// Registered once, close to the top of the middleware stack: it sees the errors returned by handlers
+// and extractors (attached to the response) as well as those of the other middlewares.
+pub async fn log_failed_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let outcome = next.call(req).await;
+    let error = match &outcome {
+        Ok(response) => response.response().error(),
+        Err(e) => Some(e),
+    };
+    if let Some(e) = error {
+        let status = e.as_response_error().status_code();
+        // Ours to fix vs. the caller's to fix
+        if status.is_server_error() {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                status = status.as_u16(),
+                "Request failed"
+            );
+        } else {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                status = status.as_u16(),
+                "Request rejected"
+            );
+        }
+    }
+    outcome
+}
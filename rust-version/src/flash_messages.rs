@@ -0,0 +1,35 @@
+//! src/flash_messages.rs
+//! One-shot messages carried across a redirect (POST-REDIRECT-GET), in a signed cookie.
+//!
+//! The middleware is registered in `startup::run`: any handler can send a `FlashMessage`
+//! (`FlashMessage::error("...").send()`) and read the pending ones through the
+//! `IncomingFlashMessages` extractor. Reading them consumes them.
+
+use std::fmt::Write;
+
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+
+use crate::html::escape_html;
+
+/// Renders the pending messages as HTML paragraphs, one CSS class per level
+/// (`flash-info`, `flash-warning`, `flash-error`).
+pub fn render(flash_messages: &IncomingFlashMessages) -> String {
+    let mut html = String::new();
+    for message in flash_messages.iter() {
+        let class = match message.level() {
+            Level::Error => "flash-error",
+            Level::Warning => "flash-warning",
+            // Debug and Success are not part of our vocabulary: shown as plain info
+            _ => "flash-info",
+        };
+        // `write!` on a String cannot fail
+        writeln!(
+            html,
+            r#"<p class="{}"><i>{}</i></p>"#,
+            class,
+            escape_html(message.content())
+        )
+        .unwrap();
+    }
+    html
+}
@@ -0,0 +1,19 @@
+//! src/html.rs
+//! What our hand-written HTML pages share.
+
+/// `s`, safe to embed in HTML text or in a quoted attribute value.
+// Anything that came from a user (or from the database, i.e. from a user) goes through here.
+pub(crate) fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
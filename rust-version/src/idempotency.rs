@@ -0,0 +1,14 @@
+//! src/idempotency.rs
+//! Making non-idempotent requests (e.g. publishing an issue) safe to retry.
+//!
+//! Clients attach an idempotency key to the request. The first request with a given
+//! (user, key) runs for real, and its response is stored in the SAME transaction as its
+//! side effects; any later request with the same key gets the stored response back.
+
+mod expiry;
+mod key;
+mod persistence;
+
+pub use expiry::*;
+pub use key::IdempotencyKey;
+pub use persistence::{NextAction, save_response, try_processing};
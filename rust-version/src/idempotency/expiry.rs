@@ -0,0 +1,51 @@
+//! src/idempotency/expiry.rs
+//! Saved responses are only kept for so long: retries happen within minutes, not weeks.
+
+use sqlx::PgPool;
+
+use crate::configuration::IdempotencySettings;
+use crate::shutdown::Shutdown;
+
+/// Purges expired keys until `shutdown` is triggered.
+// Supervised by `Application::run_until_stopped`: should it crash, the whole application
+// shuts down with it.
+pub async fn run_idempotency_expiry_until_stopped(
+    db_conn_pool: PgPool,
+    settings: IdempotencySettings,
+    shutdown: Shutdown,
+) -> Result<(), std::io::Error> {
+    while !shutdown.is_triggered() {
+        // Failures are logged by `instrument(err)`: nothing to do but try again later.
+        let _ = delete_expired_idempotency_keys(&db_conn_pool, &settings).await;
+        tokio::select! {
+            _ = tokio::time::sleep(settings.cleanup_interval()) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    tracing::info!("Idempotency key expiry stopped");
+    Ok(())
+}
+
+/// Deletes the keys older than the configured TTL, returning how many there were.
+#[tracing::instrument(skip_all, err)]
+pub async fn delete_expired_idempotency_keys(
+    db_conn_pool: &PgPool,
+    settings: &IdempotencySettings,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE created_at < now() - make_interval(secs => $1)
+        "#,
+        settings.ttl().as_secs_f64()
+    )
+    .execute(db_conn_pool)
+    .await?;
+    if result.rows_affected() > 0 {
+        tracing::info!(
+            "Deleted {} expired idempotency keys",
+            result.rows_affected()
+        );
+    }
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,29 @@
+//! src/idempotency/key.rs
+
+/// An opaque, client-chosen token: we only care that it's of a reasonable size.
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    const MAX_LENGTH: usize = 50;
+
+    pub fn parse(s: String) -> Result<Self, String> {
+        if s.is_empty() {
+            return Err("The idempotency key cannot be empty".into());
+        }
+        // Keys are stored, and part of the primary key: no unbounded inputs.
+        if s.len() >= Self::MAX_LENGTH {
+            return Err(format!(
+                "The idempotency key must be shorter than {} characters",
+                Self::MAX_LENGTH
+            ));
+        }
+        Ok(Self(s))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
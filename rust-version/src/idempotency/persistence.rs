@@ -0,0 +1,163 @@
+//! src/idempotency/persistence.rs
+
+use actix_web::HttpResponse;
+use actix_web::body::to_bytes;
+use actix_web::http::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::IdempotencyKey;
+
+// Postgres' `lock_not_available`, raised when `lock_timeout` expires
+const LOCK_NOT_AVAILABLE: &str = "55P03";
+
+/// Mirrors the `header_pair` composite type.
+// NOTE: the derive also teaches sqlx about `header_pair[]` (`_header_pair` to Postgres)
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+pub enum NextAction {
+    /// First time we see this key: go ahead, within this transaction,
+    /// and hand it back to `save_response` when done.
+    StartProcessing(Transaction<'static, Postgres>),
+    /// Seen it already: here is what we answered back then.
+    ReturnSavedResponse(HttpResponse),
+    /// The first request with this key is still running, and didn't finish in time.
+    Conflict,
+}
+
+/// Claims `idempotency_key` for this request, or retrieves the response to the previous one.
+///
+/// CONCURRENCY: the claim is an `INSERT` in a transaction which stays open until the
+/// response is saved. A duplicate arriving in the meantime blocks on the row lock until
+/// that transaction commits (and then replays its response) or rolls back (and then
+/// claims the key itself). If it waits longer than `lock_timeout`, it gets `Conflict`.
+#[tracing::instrument(skip(db_conn_pool))]
+pub async fn try_processing(
+    db_conn_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    lock_timeout: std::time::Duration,
+) -> Result<NextAction, anyhow::Error> {
+    let mut transaction = db_conn_pool.begin().await?;
+    // `SET LOCAL` does not take bind parameters: `set_config(.., is_local => true)` does.
+    sqlx::query!(
+        "SELECT set_config('lock_timeout', $1, true)",
+        format!("{}ms", lock_timeout.as_millis())
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await;
+
+    match claimed {
+        Ok(result) if result.rows_affected() > 0 => Ok(NextAction::StartProcessing(transaction)),
+        Ok(_) => match get_saved_response(db_conn_pool, idempotency_key, user_id).await? {
+            Some(saved_response) => Ok(NextAction::ReturnSavedResponse(saved_response)),
+            // Deleted by the cleanup task in between: rare enough to let the client retry
+            None => Ok(NextAction::Conflict),
+        },
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(LOCK_NOT_AVAILABLE) => {
+            Ok(NextAction::Conflict)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tracing::instrument(skip(db_conn_pool))]
+async fn get_saved_response(
+    db_conn_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            response_status_code as "response_status_code!",
+            response_headers as "response_headers!: Vec<HeaderPairRecord>",
+            response_body as "response_body!"
+        FROM idempotency
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2 AND
+            response_status_code IS NOT NULL
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(db_conn_pool)
+    .await?;
+
+    let Some(record) = saved_response else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(record.response_status_code.try_into()?)?;
+    let mut response = HttpResponse::build(status_code);
+    for HeaderPairRecord { name, value } in record.response_headers {
+        response.append_header((name, value));
+    }
+    Ok(Some(response.body(record.response_body)))
+}
+
+/// Stores `http_response` against `idempotency_key`, commits the transaction
+/// opened by `try_processing`, and hands the response back to be sent.
+#[tracing::instrument(skip(transaction, http_response))]
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    // The body is a stream: we have to buffer it to store it (and then rebuild the response)
+    let (response_head, body) = http_response.into_parts();
+    let body = to_bytes(body)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read the response body: {}", e))?;
+    let status_code = response_head.status().as_u16() as i16;
+    let headers: Vec<HeaderPairRecord> = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect();
+
+    // `query_unchecked!`: the `query!` macro can't type-check custom composite types
+    sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(response_head.set_body(body).map_into_boxed_body())
+}
@@ -0,0 +1,239 @@
+//! src/issue_delivery_worker.rs
+//! Background worker draining the `issue_delivery_queue`, one email at a time.
+//!
+//! Publishing an issue only ENQUEUES one task per recipient (see `routes::newsletters`):
+//! the actual sending happens here, outside of any HTTP request, and survives restarts
+//! since the queue lives in Postgres.
+
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{Instrument, Span, field::display};
+use uuid::Uuid;
+
+use crate::configuration::IssueDeliverySettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::metrics::Metrics;
+use crate::shutdown::Shutdown;
+use crate::telemetry::continue_trace;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i32,
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// Of the request that published it, if it was traced
+    traceparent: Option<String>,
+}
+
+/// Polls the queue until `shutdown` is triggered. The task at hand, if any, is seen
+/// through first: its email is either sent and recorded, or not sent at all.
+// One per `issue_delivery.workers`, each supervised by `Application::run_until_stopped`.
+pub async fn run_worker_until_stopped(
+    db_conn_pool: PgPool,
+    email_client: EmailClient,
+    settings: IssueDeliverySettings,
+    metrics: Metrics,
+    shutdown: Shutdown,
+) -> Result<(), std::io::Error> {
+    while !shutdown.is_triggered() {
+        let pause = match try_execute_task(&db_conn_pool, &email_client, &settings, &metrics).await
+        {
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+            Ok(ExecutionOutcome::EmptyQueue) => settings.empty_queue_poll_interval(),
+            // Most likely a database hiccup: back off a little, then try again.
+            Err(_) => std::time::Duration::from_secs(1),
+        };
+        // No point in sleeping through a shutdown
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    tracing::info!("Issue delivery worker stopped");
+    Ok(())
+}
+
+/// Picks ONE due task (if any), tries to deliver it, and records the outcome.
+#[tracing::instrument(
+    skip_all,
+    fields(newsletter_issue_id = tracing::field::Empty, subscriber_email = tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    db_conn_pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &IssueDeliverySettings,
+    metrics: &Metrics,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let Some((mut transaction, task)) = dequeue_task(db_conn_pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email));
+
+    match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => {
+            let issue = get_issue(&mut transaction, task.newsletter_issue_id).await?;
+            // Part of the publishing request's trace, rather than of the worker's polling loop:
+            // the email API gets to see that trace too (see `email_client::http`).
+            let delivery_span = tracing::info_span!("Delivering a newsletter issue");
+            if let Some(traceparent) = &issue.traceparent {
+                continue_trace(&delivery_span, traceparent);
+            }
+            match email_client
+                .send_email(
+                    &email,
+                    &issue.title,
+                    &issue.html_content,
+                    &issue.text_content,
+                )
+                .instrument(delivery_span)
+                .await
+            {
+                Ok(()) => {
+                    metrics.email_sent();
+                    delete_task(transaction, &task).await?
+                }
+                Err(e) => {
+                    metrics.email_failed();
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber."
+                    );
+                    record_failure(transaction, &task, &e.to_string(), settings).await?;
+                }
+            }
+        }
+        // Retrying won't make it valid: drop the task.
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+            );
+            delete_task(transaction, &task).await?;
+        }
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+// `FOR UPDATE`: the row stays locked until our transaction commits or rolls back.
+// `SKIP LOCKED`: other workers don't wait for it, they just pick the next row.
+// => several workers can drain the same queue concurrently, without ever
+//    sending the same email twice.
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    db_conn_pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, DeliveryTask)>, sqlx::Error> {
+    let mut transaction = db_conn_pool.begin().await?;
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_retries
+        FROM issue_delivery_queue
+        WHERE status = 'pending' AND execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    mut transaction: Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Schedules a retry, with exponential backoff, or gives up on the task
+/// (dead letter) once it has exhausted its retries.
+#[tracing::instrument(skip_all, fields(n_retries = task.n_retries))]
+async fn record_failure(
+    mut transaction: Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+    error: &str,
+    settings: &IssueDeliverySettings,
+) -> Result<(), sqlx::Error> {
+    let n_retries = task.n_retries as u32;
+    if n_retries >= settings.max_retries {
+        tracing::error!("Giving up on task: moving it to the dead-letter state.");
+        sqlx::query!(
+            r#"
+            UPDATE issue_delivery_queue
+            SET status = 'dead_letter', last_error = $3
+            WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+            "#,
+            task.newsletter_issue_id,
+            task.subscriber_email,
+            error
+        )
+        .execute(&mut *transaction)
+        .await?;
+    } else {
+        let backoff = settings.backoff(n_retries + 1);
+        sqlx::query!(
+            r#"
+            UPDATE issue_delivery_queue
+            SET n_retries = n_retries + 1,
+                execute_after = now() + make_interval(secs => $3),
+                last_error = $4
+            WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+            "#,
+            task.newsletter_issue_id,
+            task.subscriber_email,
+            backoff.as_secs_f64(),
+            error
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+    transaction: &mut Transaction<'static, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content, traceparent
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(issue)
+}
@@ -3,6 +3,21 @@
 //! Used at the top of files
 
 pub mod configuration;
+pub mod credentials;
+pub mod domain;
+pub mod email_client;
+pub mod error;
+pub mod flash_messages;
+mod html;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod metrics;
+pub mod password_policy;
+pub mod problem;
+pub mod request_id;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
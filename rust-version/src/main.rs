@@ -2,39 +2,61 @@
 //! Documents the module/crate itself
 //! Used at the top of files
 
-use std::net::TcpListener;
-
-use sqlx::PgPool;
-
 use zero2prod::configuration::get_configuration;
-use zero2prod::startup::run;
-use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::shutdown::wait_for_signal;
+use zero2prod::startup::Application;
+use zero2prod::telemetry::{
+    Redaction, build_tracer_provider, get_subscriber, init_subscriber, tracer,
+};
 
 // Attribute macro: #[...] applies transformations to the item below (func, etc...)
 // tokio::main is a procedural macro that transforms async fn main() into a proper program entry point
 // It sets up the async runtime (tokio) that can execute Futures
 // Like IORuntime.global in cats-effect - without it, async code can't run
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
+// `anyhow::Result`: should we fail to start (or to stop cleanly), the whole chain of causes
+// gets printed, not just the last one.
+async fn main() -> anyhow::Result<()> {
+    // Installed first thing: from now on, SIGTERM/SIGINT mean "stop gracefully".
+    let signal = wait_for_signal();
+
+    // Read before the subscriber is built: it says where (if anywhere) spans are exported to
+    let config = get_configuration().expect("Failed to read configuration.");
+    let redaction = Redaction::from_settings(&config.telemetry.redaction)?;
+    let tracer_provider = build_tracer_provider(&config.telemetry, redaction.clone())?;
+
     let subscriber = get_subscriber(
         // .into() - Type conversion using Into trait.
         // Compiler infers target type from context.
         // Scala equivalent: implicit conversions, but explicit call in Rust
         "zero2prod".into(),
         "info".into(),
+        // output the formatted spans to stdout
+        std::io::stdout,
+        redaction,
+        tracer_provider.as_ref().map(tracer),
     );
 
     init_subscriber(subscriber);
 
-    let config = get_configuration().expect("Failed to read configuration.");
-
-    let listener = TcpListener::bind(config.server.tcp_socket_address())
-        .expect("Failed to bind to the address");
-
-    let db_conn_pool = PgPool::connect(&config.database.connection_string())
-        .await
-        .expect("Failed to connect to Postgres");
-
-    run(listener, db_conn_pool)? // unwrapp the result of run() , i.e Result<Server, Error>
-        .await // Actually executes the Server (Future) (like unsafeRunSync in cats-effect)
+    let application = Application::build(config).await?;
+
+    let shutdown = application.shutdown_handle();
+    tokio::spawn(async move {
+        let signal = signal.await;
+        tracing::info!(signal, "Received a shutdown signal");
+        shutdown.trigger();
+    });
+
+    let outcome = application.run_until_stopped().await;
+
+    // Flushes the spans still waiting for their batch, whatever the outcome.
+    // NOTE: blocking (it waits for the exporter), hence not on the runtime's threads.
+    if let Some(provider) = tracer_provider
+        && let Err(e) = tokio::task::spawn_blocking(move || provider.shutdown()).await?
+    {
+        // Not worth failing over: the spans are lost either way
+        tracing::error!(error = %e, "Failed to flush the remaining spans");
+    }
+    Ok(outcome?)
 }
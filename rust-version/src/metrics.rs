@@ -0,0 +1,216 @@
+//! src/metrics.rs
+//! What Prometheus scrapes from `/metrics`: how the HTTP layer, the database pool and the
+//! newsletter pipeline are doing.
+//!
+//! Every label takes its values from a small, known set: route TEMPLATES (never raw paths),
+//! standard methods, status codes. An unbounded label is an unbounded number of time series.
+
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web;
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use sqlx::PgPool;
+
+use crate::telemetry::route_template;
+
+/// Every metric we expose, and the registry they are exposed from.
+/// Cheap to clone: every clone updates the same metrics.
+///
+/// # Implementation Notes
+///
+/// Our own `Registry` rather than `prometheus`'s global one: the tests run many
+/// applications in the same process, each with its own counts.
+// SCALA: like a `CollectorRegistry` threaded through the app, rather than the `defaultRegistry`
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGauge,
+    db_pool_idle_connections: IntGauge,
+    subscriptions_created: IntCounter,
+    subscriptions_confirmed: IntCounter,
+    emails_sent: IntCounter,
+    emails_failed: IntCounter,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        // NOTE: can only fail on invalid or duplicate names, i.e. a bug of ours
+        let http_requests = register(
+            &registry,
+            IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests served"),
+                &["route", "method", "status"],
+            ),
+        );
+        let http_request_duration = register(
+            &registry,
+            HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time spent serving HTTP requests",
+                ),
+                &["route", "method", "status"],
+            ),
+        );
+        let db_pool_connections = register(
+            &registry,
+            IntGauge::new(
+                "db_pool_connections",
+                "Connections currently open in the Postgres pool, idle or in use",
+            ),
+        );
+        let db_pool_idle_connections = register(
+            &registry,
+            IntGauge::new(
+                "db_pool_idle_connections",
+                "Idle connections in the Postgres pool",
+            ),
+        );
+        let subscriptions_created = register(
+            &registry,
+            IntCounter::new(
+                "subscriptions_created_total",
+                "New subscribers, pending confirmation",
+            ),
+        );
+        let subscriptions_confirmed = register(
+            &registry,
+            IntCounter::new(
+                "subscriptions_confirmed_total",
+                "Subscriptions confirmed through their link",
+            ),
+        );
+        let emails_sent = register(
+            &registry,
+            IntCounter::new(
+                "emails_sent_total",
+                "Newsletter issues delivered to a subscriber",
+            ),
+        );
+        let emails_failed = register(
+            &registry,
+            IntCounter::new(
+                "emails_failed_total",
+                "Newsletter issue deliveries that failed (retried or not)",
+            ),
+        );
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_pool_connections,
+            db_pool_idle_connections,
+            subscriptions_created,
+            subscriptions_confirmed,
+            emails_sent,
+            emails_failed,
+        }
+    }
+
+    /// `route`: a template (e.g. `/admin/password`), see `telemetry::route_template`.
+    pub fn observe_http_request(
+        &self,
+        route: &str,
+        method: &Method,
+        status: StatusCode,
+        elapsed: Duration,
+    ) {
+        let labels = [route, method_label(method), status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn subscription_created(&self) {
+        self.subscriptions_created.inc();
+    }
+
+    pub fn subscription_confirmed(&self) {
+        self.subscriptions_confirmed.inc();
+    }
+
+    pub fn email_sent(&self) {
+        self.emails_sent.inc();
+    }
+
+    pub fn email_failed(&self) {
+        self.emails_failed.inc();
+    }
+
+    /// Everything, in the Prometheus text format.
+    // The pool gauges are sampled now: a scrape is the only time anyone looks at them.
+    pub fn render(&self, db_conn_pool: &PgPool) -> Result<String, prometheus::Error> {
+        self.db_pool_connections.set(db_conn_pool.size().into());
+        self.db_pool_idle_connections
+            .set(db_conn_pool.num_idle() as i64);
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer).expect("The text format is UTF-8"))
+    }
+}
+
+fn register<M>(registry: &Registry, metric: Result<M, prometheus::Error>) -> M
+where
+    M: Collector + Clone + 'static,
+{
+    let metric = metric.expect("Invalid metric definition");
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("Metric registered twice");
+    metric
+}
+
+/// Anyone can make up a method: those we don't serve all count as one.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
+}
+
+/// Middleware: counts and times every request, by route template, method and status.
+// Registered OUTSIDE of `problem::render_problems`: the status is the one the caller got.
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let route = route_template(req.request());
+    let method = req.method().clone();
+    let start = Instant::now();
+
+    let outcome = next.call(req).await;
+
+    if let Some(metrics) = metrics {
+        let status = match &outcome {
+            Ok(response) => response.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        metrics.observe_http_request(&route, &method, status, start.elapsed());
+    }
+    outcome
+}
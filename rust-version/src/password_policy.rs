@@ -0,0 +1,63 @@
+//! src/password_policy.rs
+//! What we accept as a NEW password.
+
+use std::collections::HashSet;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::configuration::PasswordPolicySettings;
+
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    breached_passwords: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    /// Loads the breached-password list, if any, once and for all.
+    pub fn from_settings(settings: &PasswordPolicySettings) -> Result<Self, std::io::Error> {
+        let breached_passwords = match &settings.breached_passwords_path {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| {
+                    std::io::Error::new(
+                        e.kind(),
+                        format!("Failed to read the breached password list {}: {}", path, e),
+                    )
+                })?
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => HashSet::new(),
+        };
+        Ok(Self {
+            min_length: settings.min_length,
+            max_length: settings.max_length,
+            breached_passwords,
+        })
+    }
+
+    /// Returns why `password` is not acceptable, if it is not.
+    pub fn check(&self, password: &str) -> Result<(), String> {
+        // Graphemes, like `SubscriberName`: what a user would call a "character"
+        let length = password.graphemes(true).count();
+        if length < self.min_length {
+            return Err(format!(
+                "The new password must be at least {} characters long.",
+                self.min_length
+            ));
+        }
+        if length > self.max_length {
+            return Err(format!(
+                "The new password must be at most {} characters long.",
+                self.max_length
+            ));
+        }
+        if self.breached_passwords.contains(password) {
+            return Err(
+                "The new password has appeared in a data breach: please pick another one.".into(),
+            );
+        }
+        Ok(())
+    }
+}
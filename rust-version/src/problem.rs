@@ -0,0 +1,218 @@
+//! src/problem.rs
+//! Error responses, RFC 7807 style: `application/problem+json`, whoever raised the error.
+//!
+//! Three sources of errors, one format:
+//!  - our route errors: their `ResponseError::error_response` builds a `Problem`
+//!  - actix's extractors (`web::Form`, `web::Json`, `web::Query`): through the error handlers
+//!    registered in `startup::run` (see `form_error` & co.)
+//!  - everything else (e.g. a 404 for an unknown path): a generic `Problem`, from the status code
+//!
+//! `render_problems` then fills in what only the request knows: `instance` and `request_id`.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, JsonPayloadError, QueryPayloadError, UrlencodedError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+
+use crate::request_id::{REQUEST_ID_HEADER, RequestId};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// One offending field, and what's wrong with it.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct InvalidParam {
+    pub name: String,
+    pub reason: String,
+}
+
+impl InvalidParam {
+    pub fn new(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for InvalidParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.reason)
+    }
+}
+
+/// The body of every error response.
+// SCALA: a case class with a circe `Encoder`, `Option`al fields dropped rather than `null`
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Problem {
+    // NOTE: `about:blank` (RFC 7807, 4.2): the status code says it all, `title` is its reason phrase
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    /// The extension RFC 7807 uses in its own validation example.
+    #[serde(rename = "invalid-params", skip_serializing_if = "Vec::is_empty")]
+    invalid_params: Vec<InvalidParam>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail: None,
+            instance: None,
+            request_id: None,
+            invalid_params: Vec::new(),
+        }
+    }
+
+    /// What went wrong, for a human. Never for 5xx: our internals are none of the caller's business.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_invalid_params(mut self, invalid_params: Vec<InvalidParam>) -> Self {
+        self.invalid_params = invalid_params;
+        self
+    }
+
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn to_body(&self) -> BoxBody {
+        // Can't fail: plain strings and numbers all the way down
+        BoxBody::new(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// The response to send: the caller may still add headers (e.g. `WWW-Authenticate`).
+    pub fn into_response(self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code())
+            .content_type(PROBLEM_JSON)
+            .body(self.to_body());
+        // For `render_problems`, to complete it
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Middleware: turns every error response into a complete `Problem`.
+// Registered just inside `assign_request_id`: it needs the id, and must see every error.
+//
+// Left alone: anything below 400 (e.g. the 303s of our POST-REDIRECT-GET forms), and error
+// responses that already have a body of their own (e.g. the readiness report).
+pub async fn render_problems(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    // NOTE: copied out rather than keeping a clone of the `HttpRequest`: routing needs
+    // to be its only owner (it panics otherwise).
+    let instance = req.path().to_string();
+    let request_id = RequestId::of(req.request());
+    let complete = |problem: Problem| Problem {
+        instance: Some(instance),
+        request_id: request_id.as_ref().map(ToString::to_string),
+        ..problem
+    };
+
+    let (request, mut response) = match next.call(req).await {
+        Ok(response) => response.map_into_boxed_body().into_parts(),
+        // Raised by a middleware (no response to rewrite): answered with the problem instead
+        Err(e) => {
+            let status = e.as_response_error().status_code();
+            let mut response = complete(generic_problem(status, Some(&e))).into_response();
+            // `request_id::assign_request_id` never gets a response to echo the id in
+            if let Some(request_id) = &request_id {
+                response
+                    .headers_mut()
+                    .insert(REQUEST_ID_HEADER, request_id.to_header_value());
+            }
+            return Err(InternalError::from_response(e, response).into());
+        }
+    };
+    let problem = response.extensions_mut().remove::<Problem>();
+    let problem = match problem {
+        Some(problem) => problem,
+        None if !response.status().is_client_error() && !response.status().is_server_error() => {
+            return Ok(ServiceResponse::new(request, response));
+        }
+        None => match response.error() {
+            Some(e) => generic_problem(response.status(), Some(e)),
+            None if response.body().size().is_eof() => generic_problem(response.status(), None),
+            None => return Ok(ServiceResponse::new(request, response)),
+        },
+    };
+
+    let body = complete(problem).to_body();
+    let mut response = response.set_body(body);
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// For errors that don't know about `Problem`: actix's own, or other crates'.
+fn generic_problem(status: StatusCode, error: Option<&actix_web::Error>) -> Problem {
+    let problem = Problem::new(status);
+    match error {
+        Some(e) if status.is_client_error() => problem.with_detail(e.to_string()),
+        _ => problem,
+    }
+}
+
+// EXTRACTOR ERRORS
+//
+// `FormConfig`, `JsonConfig` and `QueryConfig` take these as their `error_handler`:
+// the original error stays attached (for the logs), the caller gets a `Problem`.
+
+pub fn form_error(error: UrlencodedError, _req: &HttpRequest) -> actix_web::Error {
+    let problem = match &error {
+        UrlencodedError::Parse(e) => deserialization_problem(&e.to_string()),
+        _ => Problem::new(error.status_code()).with_detail(error.to_string()),
+    };
+    InternalError::from_response(error, problem.into_response()).into()
+}
+
+pub fn json_error(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let problem = match &error {
+        JsonPayloadError::Deserialize(e) if e.is_data() => deserialization_problem(&e.to_string()),
+        _ => Problem::new(error.status_code()).with_detail(error.to_string()),
+    };
+    InternalError::from_response(error, problem.into_response()).into()
+}
+
+pub fn query_error(error: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let problem = match &error {
+        QueryPayloadError::Deserialize(e) => deserialization_problem(&e.to_string()),
+        _ => Problem::new(error.status_code()).with_detail(error.to_string()),
+    };
+    InternalError::from_response(error, problem.into_response()).into()
+}
+
+/// A 400 out of serde's message, e.g. "missing field `email`".
+// NOTE: serde only names the field for missing, unknown and duplicate ones: otherwise
+// (e.g. a wrong type) there is no field to list, the detail has to do.
+fn deserialization_problem(message: &str) -> Problem {
+    let problem = Problem::new(StatusCode::BAD_REQUEST).with_detail(message);
+    match offending_field(message) {
+        Some(name) => problem.with_invalid_params(vec![InvalidParam::new(name, message)]),
+        None => problem,
+    }
+}
+
+fn offending_field(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once("field `")?;
+    let (name, _) = rest.split_once('`')?;
+    Some(name)
+}
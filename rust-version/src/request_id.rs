@@ -0,0 +1,137 @@
+//! src/request_id.rs
+//! One id per request: in every log line it causes, and in the error responses it gets.
+//!
+//! Taken from the caller when it has one, so that our logs can be correlated with theirs:
+//!  1. an `X-Request-Id` header (e.g. set by a load balancer, or by another of our services)
+//!  2. else the trace id of a W3C `traceparent` header (e.g. set by a traced client)
+//!  3. else a fresh UUID
+//!
+//! Either way, it is echoed back in the `X-Request-Id` response header.
+
+use std::future::{Ready, ready};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(String);
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl AsRef<str> for RequestId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl RequestId {
+    /// The caller's id, if it gave us a usable one, else a new one.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        header("x-request-id")
+            .filter(|id| is_valid_request_id(id))
+            .map(str::to_string)
+            .or_else(|| {
+                header("traceparent")
+                    .and_then(TraceParent::parse)
+                    .map(|t| t.trace_id)
+            })
+            .map(RequestId)
+            .unwrap_or_else(|| RequestId(Uuid::new_v4().to_string()))
+    }
+
+    pub fn to_header_value(&self) -> HeaderValue {
+        // Can't fail: either checked by `is_valid_request_id`, or made of hex digits and dashes
+        HeaderValue::from_str(&self.0).expect("A request id is always a valid header value")
+    }
+
+    /// The id `assign_request_id` gave to `req`, if it went through it.
+    pub fn of(req: &HttpRequest) -> Option<Self> {
+        req.extensions().get::<RequestId>().cloned()
+    }
+}
+
+/// Whatever the caller sends ends up in our logs, and in our response headers:
+/// short, and harmless characters only.
+// NOTE: a rejected id is not an error: we just make up our own.
+fn is_valid_request_id(id: &str) -> bool {
+    (1..=128).contains(&id.len())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// A W3C Trace Context `traceparent` header: `{version}-{trace id}-{parent id}-{flags}`.
+#[derive(Clone, Debug)]
+pub struct TraceParent {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits: the caller's span
+    pub parent_id: String,
+}
+
+impl TraceParent {
+    /// `None` for anything malformed: per the spec, such a header is to be ignored.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (version, trace_id, parent_id, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let is_hex = |s: &str, len: usize| {
+            s.len() == len && s.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+        };
+        let is_zero = |s: &str| s.chars().all(|c| c == '0');
+        // Version 00 has exactly 4 parts, `ff` is forbidden; later versions may append more
+        let valid = is_hex(version, 2)
+            && version != "ff"
+            && (version != "00" || parts.next().is_none())
+            && is_hex(trace_id, 32)
+            && !is_zero(trace_id)
+            && is_hex(parent_id, 16)
+            && !is_zero(parent_id)
+            && is_hex(flags, 2);
+        valid.then(|| TraceParent {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+        })
+    }
+}
+
+/// Middleware: gives the request its id, and echoes it back in the response.
+// Registered as the OUTERMOST middleware: everything else (starting with the root span, see
+// `telemetry::RequestSpanBuilder`) gets to see the id.
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = RequestId::from_headers(req.headers());
+    req.extensions_mut().insert(request_id.clone());
+    let mut response = next.call(req).await?;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, request_id.to_header_value());
+    Ok(response)
+}
+
+// Handlers can ask for it as an argument, like `web::Form` or `TypedSession`.
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<RequestId, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(RequestId::of(req).ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError(
+                "No request id: is `assign_request_id` registered?",
+            )
+        }))
+    }
+}
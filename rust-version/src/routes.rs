@@ -1,5 +1,15 @@
+pub mod admin;
 pub mod health_check;
+pub mod login;
+pub mod metrics;
+pub mod newsletters;
 pub mod subscriptions;
+pub mod subscriptions_confirm;
 
+pub use admin::*;
 pub use health_check::*;
+pub use login::*;
+pub use metrics::*;
+pub use newsletters::*;
 pub use subscriptions::*;
+pub use subscriptions_confirm::*;
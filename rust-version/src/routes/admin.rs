@@ -0,0 +1,11 @@
+//! src/routes/admin.rs
+//! Everything under `/admin`: only reachable with a logged-in session
+//! (see `session_state::reject_anonymous_users`).
+
+mod dashboard;
+mod logout;
+mod password;
+
+pub use dashboard::*;
+pub use logout::*;
+pub use password::*;
@@ -0,0 +1,60 @@
+//! src/routes/admin/dashboard.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::html::escape_html;
+use crate::session_state::UserId;
+
+#[tracing::instrument(name = "Admin dashboard", skip(user_id, db_conn), fields(user_id = %*user_id))]
+pub async fn admin_dashboard(
+    // Put there by the `reject_anonymous_users` middleware
+    user_id: web::ReqData<UserId>,
+    db_conn: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Nothing the user can do about it: a plain 500 (logged by `error::log_failed_requests`)
+    let username = get_username(**user_id, &db_conn)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    // Usernames are whatever was stored: markup included
+    let username = escape_html(&username);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {username}!</p>
+    <p>Available actions:</p>
+    <ol>
+        <li><a href="/admin/password">Change password</a></li>
+    </ol>
+    <form name="logoutForm" action="/admin/logout" method="post">
+        <input type="submit" value="Logout">
+    </form>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get username", skip(db_conn))]
+pub async fn get_username(user_id: Uuid, db_conn: &PgPool) -> Result<String, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(db_conn)
+    .await?;
+    Ok(row.username)
+}
@@ -0,0 +1,152 @@
+//! src/routes/admin/password.rs
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, ResponseError, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::configuration::PasswordHashingSettings;
+use crate::credentials::{self, AuthError, Credentials, validate_credentials};
+use crate::error::error_chain_fmt;
+use crate::flash_messages;
+use crate::password_policy::PasswordPolicy;
+use crate::problem::Problem;
+use crate::routes::admin::get_username;
+use crate::session_state::{UserId, see_other};
+
+pub async fn change_password_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {}
+    <form action="/admin/password" method="post">
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            flash_messages::render(&flash_messages)
+        ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangePasswordFormData {
+    current_password: String,
+    new_password: String,
+    new_password_check: String,
+}
+
+#[tracing::instrument(
+    name = "Changing password",
+    skip(form, user_id, db_conn, policy, hashing),
+    fields(user_id = %*user_id)
+)]
+pub async fn change_password(
+    form: web::Form<ChangePasswordFormData>,
+    // Put there by the `reject_anonymous_users` middleware
+    user_id: web::ReqData<UserId>,
+    db_conn: web::Data<PgPool>,
+    policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashingSettings>,
+) -> Result<HttpResponse, ChangePasswordError> {
+    let ChangePasswordFormData {
+        current_password,
+        new_password,
+        new_password_check,
+    } = form.into_inner();
+
+    if new_password != new_password_check {
+        return Err(ChangePasswordError::Validation(
+            "You entered two different new passwords - the field values must match.".into(),
+        ));
+    }
+    policy
+        .check(&new_password)
+        .map_err(ChangePasswordError::Validation)?;
+
+    // Being logged in is not enough: whoever sits at the keyboard must know the password too.
+    let username = get_username(**user_id, &db_conn)
+        .await
+        .map_err(|e| ChangePasswordError::Storage("Failed to retrieve the username", e))?;
+    let credentials = Credentials {
+        username,
+        password: current_password,
+    };
+    validate_credentials(credentials, &hashing, &db_conn)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => ChangePasswordError::Auth(e),
+            AuthError::Unexpected(_) => ChangePasswordError::Unexpected(e.into()),
+        })?;
+
+    credentials::change_password(**user_id, new_password, &hashing, &db_conn)
+        .await
+        .context("Failed to change the password")?;
+    FlashMessage::info("Your password has been changed.").send();
+    Ok(see_other("/admin/password"))
+}
+
+#[derive(thiserror::Error)]
+pub enum ChangePasswordError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("The current password is incorrect.")]
+    Auth(#[source] AuthError),
+    #[error("{0}")]
+    Storage(&'static str, #[source] sqlx::Error),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ChangePasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ChangePasswordError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ChangePasswordError::Validation(_) | ChangePasswordError::Auth(_) => {
+                StatusCode::SEE_OTHER
+            }
+            ChangePasswordError::Storage(..) | ChangePasswordError::Unexpected(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // Every rejection is a POST-REDIRECT-GET back to the form, with the reason attached
+            ChangePasswordError::Validation(_) | ChangePasswordError::Auth(_) => {
+                FlashMessage::error(self.to_string()).send();
+                see_other("/admin/password")
+            }
+            ChangePasswordError::Storage(..) | ChangePasswordError::Unexpected(_) => {
+                Problem::new(self.status_code()).into_response()
+            }
+        }
+    }
+}
@@ -2,12 +2,182 @@
 // :: is the path/namespace separator (for modules, types, static functions)
 // . is for method calls on instances
 // Example: String::from("text") vs my_string.len()
-use actix_web::{HttpResponse, Responder};
+use std::future::Future;
+use std::time::{Duration, Instant};
 
-pub async fn health_check() -> impl Responder {
+use actix_web::{HttpResponse, Responder, web};
+use sqlx::PgPool;
+use sqlx::migrate::{Migrate, Migrator};
+
+use crate::configuration::HealthSettings;
+use crate::email_client::EmailClient;
+use crate::shutdown::Shutdown;
+
+// The migrations this binary was built with: anything not applied yet is "pending".
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(serde::Serialize)]
+struct HealthReport {
+    database: &'static str,
+}
+
+/// Always 200 while the process is up: the pool is lazy, so the application keeps
+/// serving when Postgres is down, and this tells whoever is asking whether it is.
+pub async fn health_check(
+    db_conn: web::Data<PgPool>,
+    health: web::Data<HealthSettings>,
+) -> impl Responder {
     // impl Responder = "returns some concrete type that implements the Responder trait"
     // The caller doesn't know the exact type, just that it satisfies the Responder contract
     // Similar to Scala's abstract type members or existential types
     // Traits ≈ typeclasses (behavior contracts), but impl Trait is more like bounded existentials
-    HttpResponse::Ok()
+    let database = match check_database(&db_conn, health.database_timeout()).await {
+        Ok(()) => "available",
+        Err(_) => "unavailable",
+    };
+    HttpResponse::Ok().json(HealthReport { database })
+}
+
+/// LIVENESS: is the process alive? Nothing else is checked: restarting us
+/// won't bring the database back.
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+#[derive(serde::Serialize)]
+struct ReadinessReport {
+    status: &'static str,
+    checks: Vec<CheckReport>,
+}
+
+#[derive(serde::Serialize)]
+struct CheckReport {
+    name: &'static str,
+    status: &'static str,
+    /// A failing required check makes the whole application unready.
+    required: bool,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// READINESS: should traffic be routed to us? 503 as soon as a required check fails,
+/// or the shutdown has started.
+#[tracing::instrument(name = "Readiness check", skip_all)]
+pub async fn readiness(
+    db_conn: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    health: web::Data<HealthSettings>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    // Whatever the checks say: we are on our way out, traffic should go elsewhere.
+    if shutdown.is_triggered() {
+        return HttpResponse::ServiceUnavailable().json(ReadinessReport {
+            status: "shutting_down",
+            checks: vec![],
+        });
+    }
+    // All checks run concurrently: the slowest one bounds the response time.
+    let (database, migrations, email_backend) = tokio::join!(
+        run_check(
+            "database",
+            true,
+            check_database(&db_conn, health.database_timeout())
+        ),
+        run_check(
+            "migrations",
+            true,
+            check_migrations(&db_conn, health.database_timeout())
+        ),
+        async {
+            if health.check_email_backend {
+                Some(
+                    run_check(
+                        "email_backend",
+                        false,
+                        check_email_backend(&email_client, health.email_backend_timeout()),
+                    )
+                    .await,
+                )
+            } else {
+                None
+            }
+        }
+    );
+    let checks: Vec<CheckReport> = [Some(database), Some(migrations), email_backend]
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let ready = checks
+        .iter()
+        .all(|check| !check.required || check.error.is_none());
+    let report = ReadinessReport {
+        status: if ready { "ready" } else { "not_ready" },
+        checks,
+    };
+    if ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+async fn run_check(
+    name: &'static str,
+    required: bool,
+    check: impl Future<Output = Result<(), String>>,
+) -> CheckReport {
+    let started = Instant::now();
+    let outcome = check.await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if let Err(e) = &outcome {
+        tracing::warn!(check = name, required, error = %e, "Health check failed");
+    }
+    CheckReport {
+        name,
+        status: if outcome.is_ok() { "up" } else { "down" },
+        required,
+        latency_ms,
+        error: outcome.err(),
+    }
+}
+
+/// `await`s `future`, giving up after `timeout`.
+async fn bounded<T, E: std::fmt::Display>(
+    timeout: Duration,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, String> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(outcome) => outcome.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("No answer within {}ms", timeout.as_millis())),
+    }
+}
+
+async fn check_database(db_conn: &PgPool, timeout: Duration) -> Result<(), String> {
+    bounded(timeout, sqlx::query("SELECT 1").execute(db_conn)).await?;
+    Ok(())
+}
+
+async fn check_migrations(db_conn: &PgPool, timeout: Duration) -> Result<(), String> {
+    let applied = bounded(timeout, async {
+        let mut connection = db_conn.acquire().await?;
+        connection.list_applied_migrations().await
+    })
+    .await?;
+    let pending: Vec<i64> = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .filter(|version| !applied.iter().any(|a| a.version == *version))
+        .collect();
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Pending migrations: {:?}", pending))
+    }
+}
+
+async fn check_email_backend(email_client: &EmailClient, timeout: Duration) -> Result<(), String> {
+    bounded(timeout, email_client.ping()).await
 }
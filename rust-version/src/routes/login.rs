@@ -0,0 +1,115 @@
+//! src/routes/login.rs
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::configuration::PasswordHashingSettings;
+use crate::credentials::{AuthError, Credentials, validate_credentials};
+use crate::error::error_chain_fmt;
+use crate::flash_messages;
+use crate::problem::Problem;
+use crate::session_state::{TypedSession, record_authenticated_user, see_other};
+
+pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {}
+    <form action="/login" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+            flash_messages::render(&flash_messages)
+        ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: String,
+}
+
+#[tracing::instrument(
+    name = "Logging in",
+    skip(form, db_conn, hashing, session, request),
+    fields(username = %form.username, user_id = tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<LoginFormData>,
+    db_conn: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    session: TypedSession,
+    request: HttpRequest,
+) -> Result<HttpResponse, LoginError> {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+
+    let user_id = validate_credentials(credentials, &hashing, &db_conn)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => LoginError::Auth(e),
+            AuthError::Unexpected(_) => LoginError::Unexpected(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    record_authenticated_user(&request, user_id);
+    session.renew();
+    session
+        .insert_user_id(user_id)
+        .context("Failed to store the user id in the session")?;
+    Ok(see_other("/admin/dashboard"))
+}
+
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error("Authentication failed")]
+    Auth(#[source] AuthError),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for LoginError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LoginError::Auth(_) => StatusCode::SEE_OTHER,
+            LoginError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // POST-REDIRECT-GET: back to the form.
+            // Same message whatever went wrong: no hint on whether the username exists
+            LoginError::Auth(_) => {
+                FlashMessage::error("Authentication failed.").send();
+                see_other("/login")
+            }
+            LoginError::Unexpected(_) => Problem::new(self.status_code()).into_response(),
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! src/routes/metrics.rs
+
+use actix_web::{HttpResponse, web};
+use prometheus::TEXT_FORMAT;
+use sqlx::PgPool;
+
+use crate::metrics::Metrics;
+
+/// For Prometheus to scrape: served on the admin listener if there is one (see `startup`).
+pub async fn export_metrics(
+    metrics: web::Data<Metrics>,
+    db_conn: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = metrics
+        .render(&db_conn)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(TEXT_FORMAT).body(body))
+}
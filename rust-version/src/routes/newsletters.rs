@@ -0,0 +1,268 @@
+//! src/routes/newsletters.rs
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::configuration::IdempotencySettings;
+use crate::configuration::PasswordHashingSettings;
+use crate::credentials::{AuthError, basic_authentication, validate_credentials};
+use crate::error::error_chain_fmt;
+use crate::idempotency::{IdempotencyKey, NextAction, save_response, try_processing};
+use crate::problem::{InvalidParam, Problem};
+use crate::session_state::record_authenticated_user;
+use crate::telemetry::current_traceparent;
+
+// SCALA EQUIVALENT: case class BodyData(title: String, content: Content) derives Decoder
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// The key, as a JSON body field: for clients that can't set the `Idempotency-Key` header
+    idempotency_key: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+/// What publishing an issue did: the actual sending is up to the delivery workers.
+#[derive(serde::Serialize, Debug)]
+pub struct PublishSummary {
+    pub newsletter_issue_id: Uuid,
+    /// One delivery task per confirmed subscriber.
+    pub enqueued: u64,
+}
+
+#[tracing::instrument(
+    name = "Publishing a newsletter issue",
+    skip(body, db_conn, hashing, idempotency, request),
+    fields(title = %body.title, username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn publish_newsletter(
+    // `web::Json` is to JSON what `web::Form` is to url-encoded forms:
+    // a payload that doesn't deserialize into `BodyData` is a 400, the handler never runs.
+    body: web::Json<BodyData>,
+    db_conn: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    idempotency: web::Data<IdempotencySettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    // PRIVILEGED ROUTE: only known users get to mail our whole list.
+    let credentials = basic_authentication(request.headers()).map_err(PublishError::Auth)?;
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, &hashing, &db_conn)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => PublishError::Auth(e),
+            AuthError::Unexpected(_) => PublishError::Unexpected(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    record_authenticated_user(&request, user_id);
+
+    let idempotency_key = idempotency_key(request.headers(), body.idempotency_key.clone())
+        .map_err(PublishError::Validation)?;
+
+    // The issue and its delivery tasks are written together, or not at all:
+    // a crash in between must not leave us with an issue that nobody will ever receive.
+    // With an idempotency key, that same transaction also holds the key's row lock,
+    // and saves our response: a retry can only ever see both, or neither.
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => match try_processing(
+            &db_conn,
+            idempotency_key,
+            user_id,
+            idempotency.lock_timeout(),
+        )
+        .await
+        .context("Failed to process the idempotency key")?
+        {
+            NextAction::StartProcessing(transaction) => transaction,
+            NextAction::ReturnSavedResponse(saved_response) => {
+                tracing::info!("Replaying the response saved for this idempotency key");
+                return Ok(saved_response);
+            }
+            NextAction::Conflict => return Err(PublishError::IdempotencyConflict),
+        },
+        None => db_conn
+            .begin()
+            .await
+            .map_err(|e| PublishError::Storage("Failed to open a transaction", e))?,
+    };
+
+    let newsletter_issue_id = insert_newsletter_issue(&mut transaction, &body)
+        .await
+        .map_err(|e| PublishError::Storage("Failed to store newsletter issue details", e))?;
+
+    let enqueued = enqueue_delivery_tasks(&mut transaction, newsletter_issue_id)
+        .await
+        .map_err(|e| PublishError::Storage("Failed to enqueue delivery tasks", e))?;
+
+    let summary = PublishSummary {
+        newsletter_issue_id,
+        enqueued,
+    };
+    // 202 Accepted: we took the job, it isn't done yet.
+    let response = HttpResponse::Accepted().json(&summary);
+
+    let response = match &idempotency_key {
+        // Commits, too
+        Some(idempotency_key) => save_response(transaction, idempotency_key, user_id, response)
+            .await
+            .context("Failed to save the response")?,
+        None => {
+            transaction
+                .commit()
+                .await
+                .map_err(|e| PublishError::Storage("Failed to commit transaction", e))?;
+            response
+        }
+    };
+    tracing::info!(?summary, "Newsletter issue enqueued for delivery");
+    Ok(response)
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("Authentication failed")]
+    Auth(#[source] AuthError),
+    #[error("A request with the same idempotency key is still being processed")]
+    IdempotencyConflict,
+    #[error("{0}")]
+    Storage(&'static str, #[source] sqlx::Error),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PublishError::Validation(_) => StatusCode::BAD_REQUEST,
+            PublishError::Auth(_) => StatusCode::UNAUTHORIZED,
+            PublishError::IdempotencyConflict => StatusCode::CONFLICT,
+            PublishError::Storage(..) | PublishError::Unexpected(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PublishError::Validation(reason) => Problem::new(self.status_code())
+                .with_detail(reason.clone())
+                .with_invalid_params(vec![InvalidParam::new("idempotency_key", reason)])
+                .into_response(),
+            // 401 + `WWW-Authenticate`: tells the client (e.g. a browser) which auth scheme
+            // we expect, so that it can prompt for credentials and try again.
+            PublishError::Auth(_) => {
+                let mut response = Problem::new(self.status_code())
+                    .with_detail(self.to_string())
+                    .into_response();
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="publish""#),
+                );
+                response
+            }
+            PublishError::IdempotencyConflict => Problem::new(self.status_code())
+                .with_detail(self.to_string())
+                .into_response(),
+            PublishError::Storage(..) | PublishError::Unexpected(_) => {
+                Problem::new(self.status_code()).into_response()
+            }
+        }
+    }
+}
+
+/// The `Idempotency-Key` header, or else the `idempotency_key` field: both are optional.
+fn idempotency_key(
+    headers: &HeaderMap,
+    body_field: Option<String>,
+) -> Result<Option<IdempotencyKey>, String> {
+    let from_header = headers
+        .get("Idempotency-Key")
+        .map(|value| {
+            value
+                .to_str()
+                .map(str::to_string)
+                .map_err(|_| "The 'Idempotency-Key' header was not a valid UTF8 string".to_string())
+        })
+        .transpose()?;
+    match (from_header, body_field) {
+        (Some(header), Some(field)) if header != field => {
+            Err("The 'Idempotency-Key' header and the 'idempotency_key' field disagree".into())
+        }
+        (Some(key), _) | (None, Some(key)) => IdempotencyKey::parse(key).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &BodyData,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            traceparent
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.content.text,
+        body.content.html,
+        Utc::now(),
+        // For the delivery workers to carry on with this request's trace
+        current_traceparent()
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+// A single `INSERT ... SELECT`: one task per confirmed subscriber,
+// without shipping the whole subscriber list back and forth.
+// NOTE: emails are NOT validated here: invalid ones are skipped by the workers.
+#[tracing::instrument(skip_all)]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(result.rows_affected())
+}
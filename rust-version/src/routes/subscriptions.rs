@@ -2,11 +2,18 @@
 // :: is the path/namespace separator (for modules, types, static functions)
 // . is for method calls on instances
 // Example: String::from("text") vs my_string.len()
-use actix_web::{HttpResponse, web};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, web};
 use chrono::Utc;
-use sqlx::PgPool;
-use tracing::Instrument;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
+
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionToken};
+use crate::email_client::{EmailClient, EmailError};
+use crate::error::error_chain_fmt;
+use crate::metrics::Metrics;
+use crate::problem::{InvalidParam, Problem};
+use crate::startup::ApplicationBaseUrl;
 /*
 * EXTRACTORS - Type-safe request parsing (like http4s EntityDecoder)
 *
@@ -40,6 +47,27 @@ pub struct FormData {
     name: String,
 }
 
+// TryFrom: the standard library's trait for FALLIBLE conversions.
+// Implementing it gives us `.try_into()` for free on `FormData` (via the blanket `TryInto` impl).
+// SCALA EQUIVALENT: a `def toNewSubscriber(form: FormData): ValidatedNel[InvalidParam, NewSubscriber]`
+// NOTE: every field is checked, not just up to the first bad one: the caller gets to fix them all at once.
+impl TryFrom<FormData> for NewSubscriber {
+    type Error = Vec<InvalidParam>;
+
+    fn try_from(form: FormData) -> Result<Self, Self::Error> {
+        match (
+            SubscriberName::parse(form.name),
+            SubscriberEmail::parse(form.email),
+        ) {
+            (Ok(name), Ok(email)) => Ok(Self { email, name }),
+            (name, email) => Err([("name", name.err()), ("email", email.err())]
+                .into_iter()
+                .filter_map(|(field, reason)| Some(InvalidParam::new(field, reason?)))
+                .collect()),
+        }
+    }
+}
+
 // SCALA EQUIVALENT:
 //   case req @ POST -> Root / "subscription" =>
 //     req.as[FormData].flatMap { formData => Ok() }
@@ -51,6 +79,16 @@ pub struct FormData {
 //          Type-level composition: EntityDecoder[IO, FormData] + circe Decoder
 //
 // Both achieve the same: decode failure → 400 Bad Request, success → handler runs
+//
+// A span for the handler itself, nested in the request's root span (see `telemetry::RequestSpanBuilder`):
+// associate structured information to it as a collection of key-value pairs.
+// The % symbol tells tracing to use their Display implementation for logging purposes.
+// NOTE: no `request_id` here: the root span already carries it
+#[tracing::instrument(
+    name = "Adding a new subscriber",
+    skip(_form, _db_conn, email_client, base_url, metrics),
+    fields(subscriber_email = %_form.email, subscriber_name = %_form.name)
+)]
 pub async fn subscribe(
     // web::Form<FormData> implements FromRequest trait
     // When actix-web sees this parameter:
@@ -64,7 +102,10 @@ pub async fn subscribe(
     // Retrieving a connection from the application state!
     // by getting our hands on an Arc<PgPool> in the request handler, using the web::Data extractor:
     _db_conn: web::Data<PgPool>,
-) -> HttpResponse {
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, SubscribeError> {
     // NOTE: We only return 200 OK here, but the endpoint automatically returns
     // 400 Bad Request when form data is invalid/missing.
     // This happens because web::Form<FormData> extraction fails before this handler runs,
@@ -73,58 +114,191 @@ pub async fn subscribe(
     // SCALA: Same behavior - if req.as[FormData] fails to decode, http4s middleware
     //        automatically returns 400 Bad Request via DecodeFailure handling
 
-    // unique id to CORRELATE all logs related to the same request.
-    let request_id = Uuid::new_v4();
-
-    // creating a SPAN, to capture the whole http request
-    let request_span = tracing::info_span!(
-    "Adding a new subscriber",
-        // associate structured information to our spans
-        // as a collection of key-value pairs.
-        // the % symbol tells tracing to use their Display implementation for logging purposes
-        // IMPLICITLY
-        %request_id,
-        // EXPLICITY
-        subscriber_email = %_form.email,
-        subscriber_name = %_form.name
-    );
-
-    let _request_span_guard = request_span.enter();
+    // `web::Form` only guarantees that the fields are THERE, not that they make sense:
+    // parse them into domain types before they get anywhere near the database.
+    let new_subscriber: NewSubscriber = _form.0.try_into().map_err(SubscribeError::Validation)?;
 
     // NOTE: thanks to TRACING’s log feature flag,
     // every time an event or a span are created using tracing’s macros
     // a corresponding log event is emitted, allowing loggers to pick up on it
 
-    // WARNING: we do NOT call `.enter` on the query_span !
-    // This is handled by `.instrument` (invoked downstream)
-    let query_span = tracing::info_span!("Saving new subscriber details in the database");
+    // DOUBLE OPT-IN: the subscriber row and its confirmation token are written
+    // in a single TRANSACTION: either both make it to the database, or neither does.
+    // (SCALA: like a doobie `ConnectionIO` program run with `.transact(xa)`)
+    let mut transaction = _db_conn
+        .begin()
+        .await
+        .map_err(|e| SubscribeError::Storage("Failed to open a transaction", e))?;
 
-    // `Result` of sqlx::query! has two variants: `Ok` and `Err`.
-    match sqlx::query!(
-        r#"
-        INSERT INTO subscriptions(id, email, name, subscribed_at)
-        VALUES ($1, $2, $3, $4)
-        "#,
-        Uuid::new_v4(),
-        _form.email,
-        _form.name,
-        Utc::now()
+    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+        .await
+        .map_err(|e| SubscribeError::Storage("Failed to insert the new subscriber", e))?;
+
+    let subscription_token = SubscriptionToken::generate();
+    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .map_err(|e| SubscribeError::Storage("Failed to store the subscription token", e))?;
+
+    // Sent BEFORE committing: should it fail, nothing is stored, and the caller can simply
+    // try again (rather than be left pending, with no link to click on).
+    send_confirmation_email(
+        &email_client,
+        &new_subscriber,
+        &base_url.0,
+        &subscription_token,
     )
-    .execute(_db_conn.get_ref()) // an immutable reference to the `PgPool` wrapped by `web::Data`.
-    .instrument(query_span)
     .await
-    {
-        Ok(_) => {
-            tracing::info!("request_id {request_id} - New subscriber details saved");
-            HttpResponse::Ok().finish()
+    .map_err(SubscribeError::ConfirmationEmail)?;
+
+    // NOTE: if we return early above, `transaction` is dropped without being committed,
+    // and sqlx rolls it back for us.
+    transaction
+        .commit()
+        .await
+        .map_err(|e| SubscribeError::Storage("Failed to commit the new subscriber", e))?;
+
+    tracing::info!("New subscriber details saved");
+    metrics.subscription_created();
+    Ok(HttpResponse::Ok().finish())
+}
+
+// `?` in the handler above turns into one of these: actix asks it (`ResponseError`)
+// what to answer, our middleware (`error::log_failed_requests`) logs it.
+// SCALA EQUIVALENT: a sealed trait of failures, folded into a `Response` at the edge
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    /// The form was there, but its content doesn't make sense: the caller's fault.
+    #[error("Invalid subscriber details: {}", describe(.0))]
+    Validation(Vec<InvalidParam>),
+    /// The database let us down: ours.
+    #[error("{0}")]
+    Storage(&'static str, #[source] sqlx::Error),
+    /// The email backend let us down: ours too.
+    #[error("Failed to send the confirmation email")]
+    ConfirmationEmail(#[source] EmailError),
+}
+
+// Not derived: we want the source chain, not the fields.
+impl std::fmt::Debug for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscribeError::Validation(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::Storage(..) | SubscribeError::ConfirmationEmail(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
         }
-        Err(e) => {
-            /*
-            using {:?}, the std::fmt::Debug format,
-            to capture the query error and extract as much information as possible
-            */
-            tracing::error!("request_id {request_id} - Failed to execute query:{:?}", e);
-            HttpResponse::InternalServerError().finish()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // Telling the caller what's wrong with their input is the whole point
+            SubscribeError::Validation(invalid_params) => Problem::new(self.status_code())
+                .with_detail("Invalid subscriber details")
+                .with_invalid_params(invalid_params.clone())
+                .into_response(),
+            // ... whereas our internals are none of their business
+            SubscribeError::Storage(..) | SubscribeError::ConfirmationEmail(_) => {
+                Problem::new(self.status_code()).into_response()
+            }
         }
     }
 }
+
+fn describe(invalid_params: &[InvalidParam]) -> String {
+    invalid_params
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// NOTE: thanks to TRACING’s log feature flag,
+// every time an event or a span are created using tracing’s macros
+// a corresponding log event is emitted, allowing loggers to pick up on it.
+//
+// `#[tracing::instrument]` creates a span when the function is invoked
+// and automatically attaches all the function's arguments to its context
+// (unless `skip`ped) - saving us the `info_span!` + `.instrument` dance.
+#[tracing::instrument(
+    name = "Saving new subscriber details in the database",
+    skip(transaction, new_subscriber)
+)]
+pub async fn insert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Uuid, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        "#,
+        subscriber_id,
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref(),
+        Utc::now()
+    )
+    // `Transaction` derefs to the underlying connection, which is an `Executor`
+    .execute(&mut **transaction)
+    .await?;
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(
+    name = "Storing subscription token in the database",
+    skip(transaction, subscription_token)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &SubscriptionToken,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+        subscription_token.as_ref(),
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Emails `new_subscriber` the link to `GET /subscriptions/confirm`, with their token.
+#[tracing::instrument(
+    name = "Sending a confirmation email to a new subscriber",
+    skip(email_client, new_subscriber, base_url, subscription_token)
+)]
+pub async fn send_confirmation_email(
+    email_client: &EmailClient,
+    new_subscriber: &NewSubscriber,
+    base_url: &str,
+    subscription_token: &SubscriptionToken,
+) -> Result<(), EmailError> {
+    // Tokens are alphanumeric: nothing to escape
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url.trim_end_matches('/'),
+        subscription_token.as_ref()
+    );
+    let html_body = format!(
+        "Welcome to our newsletter!<br />\
+        Click <a href=\"{}\">here</a> to confirm your subscription.",
+        confirmation_link
+    );
+    let plain_body = format!(
+        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
+        confirmation_link
+    );
+    email_client
+        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
+        .await
+}
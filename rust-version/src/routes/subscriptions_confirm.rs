@@ -0,0 +1,117 @@
+//! src/routes/subscriptions_confirm.rs
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriptionToken;
+use crate::error::error_chain_fmt;
+use crate::metrics::Metrics;
+use crate::problem::{InvalidParam, Problem};
+
+// `web::Query<Parameters>` extracts (and deserializes) the query string:
+// a missing `subscription_token` means a 400 before the handler even runs,
+// exactly like `web::Form<FormData>` for `subscribe`.
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(
+    name = "Confirming a pending subscriber",
+    skip(parameters, db_conn, metrics)
+)]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    db_conn: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, ConfirmError> {
+    // A malformed token could not have been issued by us: no need to bother the database.
+    let subscription_token = SubscriptionToken::parse(parameters.0.subscription_token)
+        .map_err(ConfirmError::Validation)?;
+
+    let subscriber_id = get_subscriber_id_from_token(&db_conn, &subscription_token)
+        .await
+        .map_err(|e| ConfirmError::Storage("Failed to look up the subscription token", e))?
+        // Well-formed, but never issued
+        .ok_or(ConfirmError::UnknownToken)?;
+
+    // IDEMPOTENT: confirming an already confirmed subscriber is a no-op `UPDATE`,
+    // so clicking twice on the link is harmless.
+    confirm_subscriber(&db_conn, subscriber_id)
+        .await
+        .map_err(|e| ConfirmError::Storage("Failed to mark the subscriber as confirmed", e))?;
+    metrics.subscription_confirmed();
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("{0}")]
+    Validation(String),
+    /// Not a token we ever issued: whoever holds it can't confirm anything.
+    #[error("Unknown subscription token")]
+    UnknownToken,
+    #[error("{0}")]
+    Storage(&'static str, #[source] sqlx::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::Validation(_) => StatusCode::BAD_REQUEST,
+            ConfirmError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmError::Storage(..) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ConfirmError::Validation(reason) => Problem::new(self.status_code())
+                .with_detail("Invalid subscription token")
+                .with_invalid_params(vec![InvalidParam::new("subscription_token", reason)])
+                .into_response(),
+            ConfirmError::UnknownToken => Problem::new(self.status_code())
+                .with_detail(self.to_string())
+                .into_response(),
+            ConfirmError::Storage(..) => Problem::new(self.status_code()).into_response(),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(db_conn))]
+pub async fn confirm_subscriber(db_conn: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id,
+    )
+    .execute(db_conn)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Get subscriber_id from token",
+    skip(db_conn, subscription_token)
+)]
+pub async fn get_subscriber_id_from_token(
+    db_conn: &PgPool,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    // `fetch_optional`: zero rows is not an error here, it's an unknown token.
+    // (SCALA: like doobie's `.option` vs `.unique`)
+    let result = sqlx::query!(
+        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        subscription_token.as_ref(),
+    )
+    .fetch_optional(db_conn)
+    .await?;
+    Ok(result.map(|r| r.subscriber_id))
+}
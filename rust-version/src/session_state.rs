@@ -0,0 +1,106 @@
+//! src/session_state.rs
+//! A typed view over the session, and the middleware guarding `/admin/*`.
+
+use std::future::{Ready, ready};
+
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::LOCATION;
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// `Session` is a stringly-typed key-value store: this wrapper is the ONLY place
+/// that knows which keys we use, and what type their values have.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+
+    /// Issues a new session key, discarding the old one.
+    // SESSION FIXATION: a session id obtained BEFORE logging in (or planted by an
+    // attacker) must not remain valid once the user is authenticated.
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+
+    /// Removes the session state, server-side AND client-side.
+    pub fn log_out(self) {
+        self.0.purge()
+    }
+}
+
+// Making `TypedSession` an extractor: handlers can ask for it as an argument,
+// exactly like they ask for `web::Form` or `web::Data`.
+impl FromRequest for TypedSession {
+    // Same error as the `Session` extractor we are wrapping
+    type Error = <Session as FromRequest>::Error;
+    // Nothing async going on: `Ready` is a future that is resolved right away
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
+
+/// The id of the logged-in user, made available to handlers behind
+/// `reject_anonymous_users` through `web::ReqData<UserId>`.
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Tells the rest of the request (starting with its root span, see
+/// `telemetry::RequestSpanBuilder`) on whose behalf it runs.
+// For the routes that authenticate on their own, outside of `reject_anonymous_users`
+pub fn record_authenticated_user(req: &HttpRequest, user_id: Uuid) {
+    req.extensions_mut().insert(UserId(user_id));
+}
+
+impl std::ops::Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Middleware: lets requests with a logged-in session through, sends everybody else to `/login`.
+// Registered with `actix_web::middleware::from_fn` on the `/admin` scope:
+// no admin handler has to remember to check the session itself.
+pub async fn reject_anonymous_users(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let session = TypedSession(req.get_session());
+
+    match session.get_user_id() {
+        Ok(Some(user_id)) => {
+            req.extensions_mut().insert(UserId(user_id));
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        Ok(None) => Ok(req.into_response(see_other("/login")).map_into_right_body()),
+        // A plain 500, logged by `error::log_failed_requests`
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+/// 303 See Other: "go GET this other page instead".
+pub fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
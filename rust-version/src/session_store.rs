@@ -0,0 +1,134 @@
+//! src/session_store.rs
+//! Where `actix-session` keeps session state, server-side.
+//!
+//! `actix-session` is generic over `SessionStore`: we ship two implementations
+//! - `InMemorySessionStore`: a process-local map. Zero setup, but sessions die with
+//!   the process and are not shared between instances.
+//! - `PostgresSessionStore`: a `sessions` table in the database we already have.
+//!
+//! `AppSessionStore` lets the configuration pick one at runtime.
+
+mod in_memory;
+mod postgres;
+
+use std::collections::HashMap;
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use sqlx::PgPool;
+
+pub use in_memory::InMemorySessionStore;
+pub use postgres::{PostgresSessionStore, delete_expired_sessions};
+
+use crate::shutdown::Shutdown;
+
+/// Which store to use, as selected by `session.store` in the configuration.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    Memory,
+    Postgres,
+}
+
+// `SessionMiddleware<S>` needs ONE concrete store type: an enum over our stores
+// (rather than a `Box<dyn SessionStore>`, which the trait's `impl Future` methods rule out).
+#[derive(Clone)]
+pub enum AppSessionStore {
+    InMemory(InMemorySessionStore),
+    Postgres(PostgresSessionStore),
+}
+
+impl AppSessionStore {
+    /// Built ONCE per application: every actix worker, and the expiry task, must share the
+    /// same store (an in-memory store per worker would log users out at random).
+    pub fn new(kind: SessionStoreKind, db_conn_pool: PgPool) -> Self {
+        match kind {
+            SessionStoreKind::Memory => AppSessionStore::InMemory(InMemorySessionStore::default()),
+            SessionStoreKind::Postgres => {
+                AppSessionStore::Postgres(PostgresSessionStore::new(db_conn_pool))
+            }
+        }
+    }
+
+    /// Drops the expired sessions, returning how many there were.
+    #[tracing::instrument(name = "Purging expired sessions", skip_all, err)]
+    pub async fn purge_expired(&self) -> Result<u64, anyhow::Error> {
+        match self {
+            AppSessionStore::InMemory(store) => store.purge_expired(),
+            AppSessionStore::Postgres(store) => Ok(store.purge_expired().await?),
+        }
+    }
+}
+
+/// Purges expired sessions every `cleanup_interval`, until `shutdown` is triggered.
+// Reads already ignore them: this only keeps the store from growing forever.
+// Supervised by `Application::run_until_stopped`, like the other background tasks.
+pub async fn run_session_expiry_until_stopped(
+    session_store: AppSessionStore,
+    cleanup_interval: std::time::Duration,
+    shutdown: Shutdown,
+) -> Result<(), std::io::Error> {
+    while !shutdown.is_triggered() {
+        // Failures are logged by `instrument(err)`: nothing to do but try again later.
+        let _ = session_store.purge_expired().await;
+        tokio::select! {
+            _ = tokio::time::sleep(cleanup_interval) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    tracing::info!("Session expiry stopped");
+    Ok(())
+}
+
+impl SessionStore for AppSessionStore {
+    async fn load(
+        &self,
+        session_key: &SessionKey,
+    ) -> Result<Option<HashMap<String, String>>, LoadError> {
+        match self {
+            AppSessionStore::InMemory(store) => store.load(session_key).await,
+            AppSessionStore::Postgres(store) => store.load(session_key).await,
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        match self {
+            AppSessionStore::InMemory(store) => store.save(session_state, ttl).await,
+            AppSessionStore::Postgres(store) => store.save(session_state, ttl).await,
+        }
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        match self {
+            AppSessionStore::InMemory(store) => store.update(session_key, session_state, ttl).await,
+            AppSessionStore::Postgres(store) => store.update(session_key, session_state, ttl).await,
+        }
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        match self {
+            AppSessionStore::InMemory(store) => store.update_ttl(session_key, ttl).await,
+            AppSessionStore::Postgres(store) => store.update_ttl(session_key, ttl).await,
+        }
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        match self {
+            AppSessionStore::InMemory(store) => store.delete(session_key).await,
+            AppSessionStore::Postgres(store) => store.delete(session_key).await,
+        }
+    }
+}
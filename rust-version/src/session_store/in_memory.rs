@@ -0,0 +1,118 @@
+//! src/session_store/in_memory.rs
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use actix_session::storage::{
+    LoadError, SaveError, SessionKey, SessionStore, UpdateError, generate_session_key,
+};
+use actix_web::cookie::time::Duration;
+
+type SessionState = HashMap<String, String>;
+
+/// Sessions in a process-local map: for tests and single-instance deployments.
+// `Arc`: cloning the store (once per actix worker) shares the SAME map.
+#[derive(Clone, Default)]
+pub struct InMemorySessionStore {
+    sessions: Arc<RwLock<HashMap<String, (SessionState, Instant)>>>,
+}
+
+impl InMemorySessionStore {
+    /// Drops the sessions past their expiry, returning how many there were.
+    // `load` evicts the expired sessions it comes across, but an abandoned one is never
+    // loaded again: without this, the map only ever grows.
+    pub fn purge_expired(&self) -> Result<u64, anyhow::Error> {
+        let mut sessions = self
+            .sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Session store lock poisoned"))?;
+        let before = sessions.len();
+        let now = Instant::now();
+        sessions.retain(|_, (_, expires_at)| *expires_at > now);
+        let purged = (before - sessions.len()) as u64;
+        if purged > 0 {
+            tracing::info!("Deleted {} expired sessions", purged);
+        }
+        Ok(purged)
+    }
+}
+
+fn expires_at(ttl: &Duration) -> Instant {
+    // A negative TTL means "already expired"
+    Instant::now() + std::time::Duration::from_secs(ttl.whole_seconds().max(0) as u64)
+}
+
+impl SessionStore for InMemorySessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let mut sessions = self
+            .sessions
+            .write()
+            .map_err(|_| LoadError::Other(anyhow::anyhow!("Session store lock poisoned")))?;
+        match sessions.get(session_key.as_ref()) {
+            Some((state, expires_at)) if *expires_at > Instant::now() => Ok(Some(state.clone())),
+            Some(_) => {
+                // Expired: evict it while we are at it
+                sessions.remove(session_key.as_ref());
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let session_key = generate_session_key();
+        self.sessions
+            .write()
+            .map_err(|_| SaveError::Other(anyhow::anyhow!("Session store lock poisoned")))?
+            .insert(
+                session_key.as_ref().to_string(),
+                (session_state, expires_at(ttl)),
+            );
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        self.sessions
+            .write()
+            .map_err(|_| UpdateError::Other(anyhow::anyhow!("Session store lock poisoned")))?
+            .insert(
+                session_key.as_ref().to_string(),
+                (session_state, expires_at(ttl)),
+            );
+        Ok(session_key)
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        if let Some((_, expiry)) = self
+            .sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Session store lock poisoned"))?
+            .get_mut(session_key.as_ref())
+        {
+            *expiry = expires_at(ttl);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        self.sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Session store lock poisoned"))?
+            .remove(session_key.as_ref());
+        Ok(())
+    }
+}
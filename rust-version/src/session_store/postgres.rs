@@ -0,0 +1,137 @@
+//! src/session_store/postgres.rs
+
+use std::collections::HashMap;
+
+use actix_session::storage::{
+    LoadError, SaveError, SessionKey, SessionStore, UpdateError, generate_session_key,
+};
+use actix_web::cookie::time::Duration;
+use sqlx::PgPool;
+use sqlx::types::Json;
+
+type SessionState = HashMap<String, String>;
+
+/// Sessions in the `sessions` table: they survive restarts and are shared by all instances.
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+    db_conn_pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(db_conn_pool: PgPool) -> Self {
+        Self { db_conn_pool }
+    }
+
+    /// See `delete_expired_sessions`.
+    pub async fn purge_expired(&self) -> Result<u64, sqlx::Error> {
+        delete_expired_sessions(&self.db_conn_pool).await
+    }
+}
+
+fn ttl_seconds(ttl: &Duration) -> f64 {
+    ttl.as_seconds_f64()
+}
+
+impl SessionStore for PostgresSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        // Expired sessions are simply invisible.
+        let row = sqlx::query!(
+            r#"
+            SELECT state AS "state: Json<SessionState>"
+            FROM sessions
+            WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref()
+        )
+        .fetch_optional(&self.db_conn_pool)
+        .await
+        .map_err(|e| LoadError::Other(e.into()))?;
+        Ok(row.map(|row| row.state.0))
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let session_key = generate_session_key();
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, state, expires_at)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            "#,
+            session_key.as_ref(),
+            Json(&session_state) as _,
+            ttl_seconds(ttl)
+        )
+        .execute(&self.db_conn_pool)
+        .await
+        .map_err(|e| SaveError::Other(e.into()))?;
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        // UPSERT: the row may have expired (and been cleaned up) in the meantime
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, state, expires_at)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            ON CONFLICT (session_key) DO UPDATE
+            SET state = EXCLUDED.state, expires_at = EXCLUDED.expires_at
+            "#,
+            session_key.as_ref(),
+            Json(&session_state) as _,
+            ttl_seconds(ttl)
+        )
+        .execute(&self.db_conn_pool)
+        .await
+        .map_err(|e| UpdateError::Other(e.into()))?;
+        Ok(session_key)
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET expires_at = now() + make_interval(secs => $2)
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref(),
+            ttl_seconds(ttl)
+        )
+        .execute(&self.db_conn_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_key = $1"#,
+            session_key.as_ref()
+        )
+        .execute(&self.db_conn_pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Deletes the sessions past their `expires_at`, returning how many there were.
+#[tracing::instrument(skip_all)]
+pub async fn delete_expired_sessions(db_conn_pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(r#"DELETE FROM sessions WHERE expires_at <= now()"#)
+        .execute(db_conn_pool)
+        .await?;
+    if result.rows_affected() > 0 {
+        tracing::info!("Deleted {} expired sessions", result.rows_affected());
+    }
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,120 @@
+//! src/shutdown.rs
+//! Stopping in an orderly fashion: finish what was started, then let go of the database.
+//!
+//! The sequence, on SIGTERM (e.g. a rolling deployment) or SIGINT (Ctrl+C):
+//!  1. `Shutdown::trigger`: `/health/ready` starts failing, background workers are told to stop
+//!  2. after `shutdown.readiness_delay_milliseconds`, the HTTP server stops accepting connections, and drains in-flight requests
+//!     for at most `shutdown.grace_period_seconds`
+//!  3. workers finish their current unit of work (same deadline)
+//!  4. the `PgPool` is closed
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// A broadcast, one-shot, "time to stop" flag.
+/// Cheap to clone: every clone observes the same flag.
+// SCALA: a `Deferred[IO, Unit]` completed once, awaited by many fibers.
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: std::sync::Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: std::sync::Arc::new(sender),
+        }
+    }
+
+    /// Idempotent: triggering twice is the same as triggering once.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once `trigger` has been called (immediately, if it already was).
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // Can't fail: we hold the sender ourselves.
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Drives `task` to completion, under supervision:
+    ///  - if it stops on its own (i.e. it crashed), the shutdown is triggered:
+    ///    one component down brings the whole process down with it
+    ///  - once the shutdown is triggered, it gets `grace_period` to wrap up, then is dropped.
+    // SCALA: roughly `task.guarantee(shutdown.complete).timeoutTo(...)` in cats-effect.
+    pub async fn supervise<F>(
+        &self,
+        name: &'static str,
+        task: F,
+        grace_period: Duration,
+    ) -> Result<(), std::io::Error>
+    where
+        F: Future<Output = Result<(), std::io::Error>>,
+    {
+        let mut task = std::pin::pin!(task);
+        let outcome = tokio::select! {
+            outcome = &mut task => outcome,
+            _ = self.triggered() => match tokio::time::timeout(grace_period, &mut task).await {
+                Ok(outcome) => outcome,
+                Err(_) => {
+                    tracing::error!(
+                        task = name,
+                        grace_period_seconds = grace_period.as_secs(),
+                        "Did not stop within the grace period: cut short"
+                    );
+                    Ok(())
+                }
+            },
+        };
+        if !self.is_triggered() {
+            tracing::error!(task = name, "Stopped unexpectedly: shutting down");
+            self.trigger();
+        }
+        if let Err(e) = &outcome {
+            tracing::error!(task = name, error = %e, "Stopped with an error");
+        }
+        outcome
+    }
+}
+
+/// Resolves on the first SIGTERM or SIGINT, returning its name.
+// NOT an `async fn`: the handlers are installed right away, rather than on first poll,
+// so that a signal received in between doesn't kill the process the default way.
+pub fn wait_for_signal() -> impl Future<Output = &'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate =
+            signal(SignalKind::terminate()).expect("Failed to install the SIGTERM handler");
+        let mut interrupt =
+            signal(SignalKind::interrupt()).expect("Failed to install the SIGINT handler");
+        async move {
+            tokio::select! {
+                _ = terminate.recv() => "SIGTERM",
+                _ = interrupt.recv() => "SIGINT",
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        async {
+            let _ = tokio::signal::ctrl_c().await;
+            "SIGINT"
+        }
+    }
+}
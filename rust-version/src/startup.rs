@@ -1,15 +1,364 @@
-use actix_web::middleware::Logger;
+use actix_session::SessionMiddleware;
+use actix_session::config::BrowserSession;
+use actix_web::cookie::{Key, time::Duration};
+use actix_web::middleware::from_fn;
 use actix_web::{App, HttpServer, dev::Server, web};
+use actix_web_flash_messages::FlashMessagesFramework;
+use actix_web_flash_messages::storage::CookieMessageStore;
+use anyhow::Context;
+use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use std::net::TcpListener;
 
-use crate::routes::health_check;
-use crate::routes::subscribe;
+use tokio::task::JoinSet;
+use tracing_actix_web::TracingLogger;
 
-// NOTE: pub fn: public since it is not a binary entrypoint
-pub fn run(listener: TcpListener, db_conn_pool: PgPool) -> Result<Server, std::io::Error> {
+use crate::configuration::{
+    DatabaseSettings, DatabaseStartupProbeSettings, IdempotencySettings, IssueDeliverySettings,
+    SessionSettings, Settings,
+};
+use crate::email_client::EmailClient;
+use crate::error::log_failed_requests;
+use crate::idempotency::run_idempotency_expiry_until_stopped;
+use crate::issue_delivery_worker::run_worker_until_stopped;
+use crate::metrics::{Metrics, record_http_metrics};
+use crate::password_policy::PasswordPolicy;
+use crate::problem::{form_error, json_error, query_error, render_problems};
+use crate::request_id::assign_request_id;
+use crate::routes::{
+    admin_dashboard, change_password, change_password_form, confirm, export_metrics, health_check,
+    liveness, log_out, login, login_form, publish_newsletter, readiness, subscribe,
+};
+use crate::session_state::reject_anonymous_users;
+use crate::session_store::{AppSessionStore, run_session_expiry_until_stopped};
+use crate::shutdown::Shutdown;
+use crate::telemetry::RequestSpanBuilder;
+
+/// The whole application, built and ready to go: the listener is already bound
+/// (hence a known `port`, even when asked for port 0), the HTTP server and the
+/// background workers start as soon as `run_until_stopped` is awaited.
+///
+/// `main` and the test harness both go through here: what is tested is what is deployed.
+pub struct Application {
+    port: u16,
+    server: Server,
+    // Serves `/metrics`, when `server.admin_port` is set
+    admin_port: Option<u16>,
+    admin_server: Option<Server>,
+    metrics: Metrics,
+    db_conn_pool: PgPool,
+    // One per delivery worker: each gets its own client (and its own pool of
+    // connections to the email backend)
+    worker_email_clients: Vec<EmailClient>,
+    issue_delivery: IssueDeliverySettings,
+    idempotency: IdempotencySettings,
+    session: SessionSettings,
+    session_store: AppSessionStore,
+    readiness_delay: std::time::Duration,
+    grace_period: std::time::Duration,
+    shutdown: Shutdown,
+}
+
+impl Application {
+    pub async fn build(settings: Settings) -> Result<Self, anyhow::Error> {
+        let db_conn_pool = get_connection_pool(&settings.database);
+        // Serving with the database down beats not serving at all: the health endpoints
+        // will say so, and requests that don't need it still work.
+        if wait_for_database(&db_conn_pool, &settings.database.startup_probe)
+            .await
+            .is_err()
+        {
+            tracing::warn!("Starting without a database connection");
+        }
+
+        let email_client = build_email_client(&settings)?;
+        let worker_email_clients = (0..settings.issue_delivery.workers)
+            .map(|_| build_email_client(&settings))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let address = settings.server.tcp_socket_address();
+        let listener = TcpListener::bind(&address)
+            .with_context(|| format!("Failed to bind to {}", address))?;
+        // We retrieve the port assigned to us by the OS
+        let port = listener.local_addr()?.port();
+        let admin_listener = settings
+            .server
+            .admin_tcp_socket_address()
+            .map(|address| {
+                TcpListener::bind(&address)
+                    .with_context(|| format!("Failed to bind the admin listener to {}", address))
+            })
+            .transpose()?;
+        let admin_port = admin_listener
+            .as_ref()
+            .map(|listener| listener.local_addr().map(|address| address.port()))
+            .transpose()?;
+
+        let metrics = Metrics::new();
+        let admin_server = admin_listener
+            .map(|listener| run_admin(listener, db_conn_pool.clone(), metrics.clone()))
+            .transpose()
+            .context("Failed to build the admin HTTP server")?;
+
+        let shutdown = Shutdown::new();
+        let issue_delivery = settings.issue_delivery.clone();
+        let idempotency = settings.idempotency.clone();
+        let session = settings.session.clone();
+        let session_store = AppSessionStore::new(session.store, db_conn_pool.clone());
+        let readiness_delay = settings.shutdown.readiness_delay();
+        let grace_period = settings.shutdown.grace_period();
+        let server = run(
+            listener,
+            db_conn_pool.clone(),
+            email_client,
+            session_store.clone(),
+            metrics.clone(),
+            settings,
+            shutdown.clone(),
+        )
+        .context("Failed to build the HTTP server")?;
+
+        Ok(Self {
+            port,
+            server,
+            admin_port,
+            admin_server,
+            metrics,
+            db_conn_pool,
+            worker_email_clients,
+            issue_delivery,
+            idempotency,
+            session,
+            session_store,
+            readiness_delay,
+            grace_period,
+            shutdown,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// `None` if `/metrics` is served on `port`, with everything else.
+    pub fn admin_port(&self) -> Option<u16> {
+        self.admin_port
+    }
+
+    /// What the application counts: for whoever sends emails on its behalf (e.g. the tests).
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Triggering it stops the application, gracefully: see `run_until_stopped`.
+    // Signals are the caller's business: tests run many applications in the same process.
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Serves, and runs the background workers, until the shutdown is triggered
+    /// (or one of them crashes, which triggers it). Then:
+    ///  - `/health/ready` fails, and the server keeps serving for `shutdown.readiness_delay_milliseconds`
+    ///  - the server stops accepting connections, and drains the in-flight requests
+    ///  - the workers finish their current unit of work
+    ///  - the `PgPool` is closed
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        let Self {
+            server,
+            admin_server,
+            metrics,
+            db_conn_pool,
+            worker_email_clients,
+            issue_delivery,
+            idempotency,
+            session,
+            session_store,
+            readiness_delay,
+            grace_period,
+            shutdown,
+            ..
+        } = self;
+
+        let server_handle = server.handle();
+        let admin_server_handle = admin_server.as_ref().map(Server::handle);
+        let stop_server = async {
+            shutdown.triggered().await;
+            if !readiness_delay.is_zero() {
+                tracing::info!(
+                    readiness_delay_milliseconds = readiness_delay.as_millis() as u64,
+                    "Shutting down: failing readiness, still serving until traffic moves elsewhere"
+                );
+                tokio::time::sleep(readiness_delay).await;
+            }
+            tracing::info!(
+                grace_period_seconds = grace_period.as_secs(),
+                "Shutting down: draining in-flight requests and background work"
+            );
+            server_handle.stop(true).await;
+            // Scraped until the very end
+            if let Some(admin_server_handle) = admin_server_handle {
+                admin_server_handle.stop(true).await;
+            }
+        };
+
+        let mut delivery_workers = JoinSet::new();
+        for email_client in worker_email_clients {
+            let worker = run_worker_until_stopped(
+                db_conn_pool.clone(),
+                email_client,
+                issue_delivery.clone(),
+                metrics.clone(),
+                shutdown.clone(),
+            );
+            let shutdown = shutdown.clone();
+            delivery_workers.spawn(async move {
+                shutdown
+                    .supervise("issue_delivery_worker", worker, grace_period)
+                    .await
+            });
+        }
+        let delivery_workers = async {
+            let mut outcome = Ok(());
+            while let Some(joined) = delivery_workers.join_next().await {
+                let worker_outcome = joined.unwrap_or_else(|e| {
+                    tracing::error!(error = %e, "An issue delivery worker panicked: shutting down");
+                    shutdown.trigger();
+                    Err(std::io::Error::other(e))
+                });
+                outcome = outcome.and(worker_outcome);
+            }
+            outcome
+        };
+        let idempotency_expiry = run_idempotency_expiry_until_stopped(
+            db_conn_pool.clone(),
+            idempotency,
+            shutdown.clone(),
+        );
+
+        // All futures are driven CONCURRENTLY, on the same runtime, until they have ALL stopped:
+        // whichever stops first (i.e. crashes) brings the others down with it, gracefully.
+        // SCALA EQUIVALENT: (server, worker).parTupled, with a shared `Deferred` to stop them
+        let admin_server = async {
+            match admin_server {
+                Some(admin_server) => {
+                    shutdown
+                        .supervise("admin_server", admin_server, readiness_delay + grace_period)
+                        .await
+                }
+                None => Ok(()),
+            }
+        };
+        // Whichever the store: abandoned sessions are never loaded again, hence never evicted
+        let session_expiry = run_session_expiry_until_stopped(
+            session_store,
+            session.cleanup_interval(),
+            shutdown.clone(),
+        );
+        let (server, admin_server, delivery_workers, idempotency_expiry, session_expiry, ()) = tokio::join!(
+            // The server only starts draining once the readiness delay is over
+            shutdown.supervise("http_server", server, readiness_delay + grace_period),
+            admin_server,
+            delivery_workers,
+            shutdown.supervise("idempotency_expiry", idempotency_expiry, grace_period),
+            shutdown.supervise("session_expiry", session_expiry, grace_period),
+            stop_server,
+        );
+
+        // Nobody is using the pool anymore: tell Postgres we are leaving, rather than vanishing.
+        db_conn_pool.close().await;
+        tracing::info!("Database connection pool closed: shutdown complete");
+
+        server
+            .and(admin_server)
+            .and(delivery_workers)
+            .and(idempotency_expiry)
+            .and(session_expiry)
+    }
+}
+
+fn build_email_client(settings: &Settings) -> Result<EmailClient, anyhow::Error> {
+    settings
+        .email_client
+        .clone()
+        .client()
+        .map_err(|e| anyhow::anyhow!("Invalid email client configuration: {}", e))
+}
+
+/// The one way to build a pool: TLS mode, sizing and timeouts all come from `DatabaseSettings`.
+// LAZY: no connection is opened until the first query, so a database that is down
+// (e.g. mid-rollout) doesn't keep the application from starting.
+pub fn get_connection_pool(database: &DatabaseSettings) -> PgPool {
+    database
+        .pool_options()
+        .connect_lazy_with(database.with_db())
+}
+
+/// Retries `SELECT 1`, with exponential backoff, until the database answers
+/// or `max_attempts` is reached. Does nothing if the probe is disabled.
+#[tracing::instrument(name = "Wait for the database", skip_all)]
+pub async fn wait_for_database(
+    db_conn_pool: &PgPool,
+    probe: &DatabaseStartupProbeSettings,
+) -> Result<(), sqlx::Error> {
+    if !probe.enabled {
+        return Ok(());
+    }
+    let mut attempt = 1;
+    loop {
+        match sqlx::query("SELECT 1").execute(db_conn_pool).await {
+            Ok(_) => {
+                tracing::info!(attempt, "The database is available");
+                return Ok(());
+            }
+            Err(e) if attempt >= probe.max_attempts => {
+                tracing::error!(attempt, error = %e, "Gave up waiting for the database");
+                return Err(e);
+            }
+            Err(e) => {
+                let backoff = probe.backoff(attempt);
+                tracing::warn!(
+                    attempt,
+                    max_attempts = probe.max_attempts,
+                    backoff_milliseconds = backoff.as_millis() as u64,
+                    error = %e,
+                    "The database is not available yet, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Where the application can be reached from the outside (`application.base_url`):
+/// a type of its own, so that handlers can ask for it as `web::Data<ApplicationBaseUrl>`.
+pub struct ApplicationBaseUrl(pub String);
+
+// NOTE: not `pub`: `Application` is the way in
+fn run(
+    listener: TcpListener,
+    db_conn_pool: PgPool,
+    email_client: EmailClient,
+    session_store: AppSessionStore,
+    metrics: Metrics,
+    settings: Settings,
+    shutdown: Shutdown,
+) -> Result<Server, std::io::Error> {
     // Result is left-biased vs. Scala Either 'conventionally' right-biased
 
+    // The listener, the pool, the email client and the session store are already built:
+    // only the settings of the web layer itself are left to pick.
+    let Settings {
+        application: application_settings,
+        server: server_settings,
+        session: session_settings,
+        password: password_settings,
+        idempotency: idempotency_settings,
+        health: health_settings,
+        shutdown: shutdown_settings,
+        ..
+    } = settings;
+
     /*
      * web::Data wraps our connection in an Atomic Reference Counted pointer, an Arc:
      * each instance of the application, instead of getting a raw copy of a PgPool,
@@ -18,6 +367,39 @@ pub fn run(listener: TcpListener, db_conn_pool: PgPool) -> Result<Server, std::i
      * and hands over a new copy of the memory address of the wrapped value.
      */
     let wrapped_clonable_db_conn = web::Data::new(db_conn_pool);
+    // Same story for the email client: one instance (and one HTTP connection pool),
+    // shared by all workers.
+    let email_client = web::Data::new(email_client);
+    let base_url = web::Data::new(ApplicationBaseUrl(application_settings.base_url));
+    // Misconfigured hashing parameters, or a missing breached-password list: better to
+    // find out now than on the first login.
+    password_settings.hashing.params().map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid `password.hashing` parameters: {}", e),
+        )
+    })?;
+    let password_policy = web::Data::new(PasswordPolicy::from_settings(&password_settings.policy)?);
+    let password_hashing = web::Data::new(password_settings.hashing);
+    let idempotency_settings = web::Data::new(idempotency_settings);
+    let health_settings = web::Data::new(health_settings);
+    let shutdown = web::Data::new(shutdown);
+    let metrics = web::Data::new(metrics);
+    // Unless the admin listener serves it
+    let serve_metrics = server_settings.admin_port.is_none();
+
+    // Signs the session and flash message cookies, so that clients cannot forge or tamper with them.
+    // Read from the configuration: every replica (and every restart) must share the same key.
+    let hmac_key = Key::try_from(application_settings.hmac_secret.expose_secret().as_bytes())
+        .map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid `application.hmac_secret`: {}", e),
+            )
+        })?;
+    // Flash messages survive exactly one redirect: they are removed as soon as they are read.
+    let message_store = CookieMessageStore::builder(hmac_key.clone()).build();
+    let message_framework = FlashMessagesFramework::builder(message_store).build();
 
     // HttpServer handles all transport level concerns
     let server = HttpServer::new(
@@ -32,22 +414,79 @@ pub fn run(listener: TcpListener, db_conn_pool: PgPool) -> Result<Server, std::i
             // App is the component whose job is to take an incoming request as input and spit out a response.
             App::new()
                 // Adding Middlewares with the `wrap` method on `App`
-                .wrap(Logger::default()) // emits a log record for every incoming request.
+                // Any handler can now send `FlashMessage`s and read `IncomingFlashMessages`
+                .wrap(message_framework.clone())
+                .wrap(
+                    SessionMiddleware::builder(session_store.clone(), hmac_key.clone())
+                        .cookie_secure(session_settings.cookie_secure)
+                        .session_lifecycle(
+                            BrowserSession::default()
+                                .state_ttl(Duration::minutes(session_settings.ttl_minutes)),
+                        )
+                        .build(),
+                )
+                .wrap(from_fn(log_failed_requests))
+                // Every error response, whoever raised it, as `application/problem+json`
+                .wrap(from_fn(render_problems))
+                // Counted as the caller saw it: after `render_problems`, e.g. 404s included
+                .wrap(from_fn(record_http_metrics))
+                // One span per request, around everything else: every log line within
+                // a request carries its id, and the span ends with its status and latency
+                .wrap(TracingLogger::<RequestSpanBuilder>::new())
+                // Registered LAST, hence the OUTERMOST: the id is settled before anything else runs
+                .wrap(from_fn(assign_request_id))
                 .route(
                     "/health_check",
                     // web::get() creates a route guard that only matches HTTP GET requests
                     // .to(health_check) binds the greet handler function to this route
                     web::get().to(health_check),
                 )
+                // Probes, for the orchestrator: is the process alive? Should it get traffic?
+                .route("/health/live", web::get().to(liveness))
+                .route("/health/ready", web::get().to(readiness))
+                .configure(|config| {
+                    if serve_metrics {
+                        config.route("/metrics", web::get().to(export_metrics));
+                    }
+                })
                 .route(
                     "/subscription",           // PATH: &str
                     web::post().to(subscribe), // ROUTE: Route (an instance of the Route struct)
                 )
+                .route("/subscriptions/confirm", web::get().to(confirm))
+                .route("/newsletters", web::post().to(publish_newsletter))
+                .route("/login", web::get().to(login_form))
+                .route("/login", web::post().to(login))
+                .service(
+                    // Every route in this scope goes through `reject_anonymous_users` first
+                    web::scope("/admin")
+                        .wrap(from_fn(reject_anonymous_users))
+                        .route("/dashboard", web::get().to(admin_dashboard))
+                        .route("/password", web::get().to(change_password_form))
+                        .route("/password", web::post().to(change_password))
+                        .route("/logout", web::post().to(log_out)),
+                )
                 // Register a PgPool as part of our application state
                 // byt getting a pointer copy and attach it to the application state
                 .app_data(wrapped_clonable_db_conn.clone())
+                .app_data(email_client.clone())
+                .app_data(base_url.clone())
+                .app_data(password_policy.clone())
+                .app_data(password_hashing.clone())
+                .app_data(idempotency_settings.clone())
+                .app_data(health_settings.clone())
+                .app_data(shutdown.clone())
+                .app_data(metrics.clone())
+                // Extractor failures (i.e. the handler never ran) get a `Problem` too
+                .app_data(web::FormConfig::default().error_handler(form_error))
+                .app_data(web::JsonConfig::default().error_handler(json_error))
+                .app_data(web::QueryConfig::default().error_handler(query_error))
         },
     )
+    // Once stopped, in-flight requests get this long to complete
+    .shutdown_timeout(shutdown_settings.grace_period().as_secs())
+    // Signals are the caller's business: it also has background workers to stop (see `shutdown`)
+    .disable_signals()
     .listen(listener)?
     .run(); // Returns a Future (NOTA: lazy in rust - pure description of work - doesn't execute yet!)
 
@@ -55,3 +494,26 @@ pub fn run(listener: TcpListener, db_conn_pool: PgPool) -> Result<Server, std::i
     // i.e, it can run in the background, concurrently with downstream futures and tasks
     Ok(server) // NOTE: Server IS A FUTURE WRAPPED IN A RESULT !!!
 }
+
+/// `/metrics`, alone on its own listener: for the scraper, out of the public listener's reach.
+// NOTE: not traced, nor counted: a scrape every few seconds would drown everything else.
+fn run_admin(
+    listener: TcpListener,
+    db_conn_pool: PgPool,
+    metrics: Metrics,
+) -> Result<Server, std::io::Error> {
+    let db_conn_pool = web::Data::new(db_conn_pool);
+    let metrics = web::Data::new(metrics);
+    let server = HttpServer::new(move || {
+        App::new()
+            .route("/metrics", web::get().to(export_metrics))
+            .app_data(db_conn_pool.clone())
+            .app_data(metrics.clone())
+    })
+    // A single worker is plenty for a scraper
+    .workers(1)
+    .disable_signals()
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
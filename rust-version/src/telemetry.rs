@@ -1,31 +1,79 @@
-use tracing::Subscriber;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::{Error, HttpMessage, HttpRequest};
+use anyhow::Context as _;
+use hmac::{Hmac, Mac};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{
+    BatchSpanProcessor, Sampler, SdkTracer, SdkTracerProvider, SpanData, SpanProcessor,
+};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use tokio::task::JoinHandle;
 use tracing::subscriber::set_global_default;
+use tracing::{Span, Subscriber};
+use tracing_actix_web::RootSpanBuilder;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt};
+use validator::ValidateEmail;
+
+use crate::configuration::{OtlpProtocol, RedactionMode, RedactionSettings, TelemetrySettings};
+use crate::request_id::{RequestId, TraceParent};
+use crate::session_state::UserId;
 
 /// Compose multiple layers into a `tracing`'s subscriber.
 ///
+/// `env_filter` is the fallback for when `RUST_LOG` is unset (or unparsable): e.g. `"info"`.
+/// `sink` is where the formatted records go: `std::io::stdout` for the application,
+/// `std::io::sink` for the tests (unless asked otherwise, see `TEST_LOG` in `tests/api/helpers.rs`).
+/// `redaction` is what becomes of the PII on its way to `sink` (see `Redaction`).
+/// `tracer`, if any, is where spans are exported to as well (see `build_tracer_provider`).
+///
 /// # Implementation Notes
 ///
 /// We are using `impl Subscriber` as return type to avoid having to
 /// spell out the actual type of the returned subscriber, which is indeed quite complex.
 /// We need to explicitly call out that the returned subscriber is
 /// `Send` and `Sync` to make it possible to pass it to `init_subscriber` later on.
-pub fn get_subscriber(name: String, env_filter: String) -> impl Subscriber + Send + Sync {
-    // NOTE: Should be moved elsewhere ? in configuration ?
-    // 12-factor: RUST_LOG is required config, fail fast if missing
-    std::env::var("RUST_LOG")
-        .expect("RUST_LOG environment variable must be set (e.g., RUST_LOG=info)");
-
-    let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+///
+/// `Sink` is a HIGHER-RANKED trait bound: `MakeWriter<'a>` must hold for every lifetime `'a`,
+/// i.e. the writers it makes may borrow from it, for however long a record takes to write.
+// SCALA: roughly `Sink: [A] => MakeWriter[A]`, a polymorphic function type
+pub fn get_subscriber<Sink>(
+    name: String,
+    env_filter: String,
+    sink: Sink,
+    redaction: Redaction,
+    tracer: Option<SdkTracer>,
+) -> impl Subscriber + Send + Sync
+where
+    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    // NOTE: `RUST_LOG` wins when set; a missing one is not worth crashing over.
+    // An EMPTY one counts as missing: `EnvFilter` would read it as "errors only".
+    let env_filter = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directives| !directives.trim().is_empty())
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| EnvFilter::new(env_filter));
 
-    let formatting_layer = BunyanFormattingLayer::new(
-        name,
-        // output the formatted spans to stdout
-        std::io::stdout,
-    );
+    let formatting_layer = BunyanFormattingLayer::new(name, Redacting::new(sink, redaction));
+    // An `Option<Layer>` is a layer too: `None` does nothing
+    let otel_layer = tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
 
     Registry::default()
         // `.with` is provided by `SubscriberExt`
@@ -33,6 +81,367 @@ pub fn get_subscriber(name: String, env_filter: String) -> impl Subscriber + Sen
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(otel_layer)
+}
+
+/// Span and event fields that identify a person: logged as `Redaction` says.
+/// Tagging a field as PII is adding its name here.
+pub const PII_FIELDS: &[&str] = &["subscriber_email", "subscriber_name", "username"];
+
+/// Fields that may QUOTE someone's email address, e.g. an SMTP relay's
+/// "550 5.1.1 <ursula_le_guin@gmail.com>: Recipient address rejected" in an error's source chain:
+/// the addresses found in them are redacted like the `PII_FIELDS`, the rest is kept.
+pub const ERROR_FIELDS: &[&str] = &["error.cause_chain", "error.message"];
+
+/// What becomes of the `PII_FIELDS` in the logs: see `RedactionMode`.
+#[derive(Clone)]
+pub enum Redaction {
+    Off,
+    Mask,
+    Hash(Hmac<Sha256>),
+}
+
+impl Redaction {
+    pub fn from_settings(settings: &RedactionSettings) -> Result<Self, anyhow::Error> {
+        match settings.mode {
+            RedactionMode::Off => Ok(Redaction::Off),
+            RedactionMode::Mask => Ok(Redaction::Mask),
+            RedactionMode::Hash => {
+                let key = settings
+                    .key
+                    .as_ref()
+                    .context("`telemetry.redaction.key` is required to hash PII")?;
+                anyhow::ensure!(
+                    key.expose_secret().len() >= 32,
+                    "`telemetry.redaction.key` must be at least 32 bytes long"
+                );
+                let mac = Hmac::new_from_slice(key.expose_secret().as_bytes())
+                    .expect("HMAC takes keys of any size");
+                Ok(Redaction::Hash(mac))
+            }
+        }
+    }
+
+    /// `value`, as it may be logged.
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Redaction::Off => value.to_string(),
+            Redaction::Mask => "[REDACTED]".to_string(),
+            // Same value, same hash: records about the same person can still be correlated
+            Redaction::Hash(mac) => {
+                let mut mac = mac.clone();
+                mac.update(value.as_bytes());
+                // 128 bits are plenty to tell values apart
+                format!("hmac:{}", hex::encode(&mac.finalize().into_bytes()[..16]))
+            }
+        }
+    }
+
+    /// `text`, with the email addresses it quotes redacted: `None` if it quotes none.
+    // An address is a run of the characters addresses are made of, that `validator` (as in
+    // `SubscriberEmail::parse`) accepts: `<`, `>`, quotes, `:`, whitespace... all end one.
+    fn redact_email_addresses(&self, text: &str) -> Option<String> {
+        let is_address_char = |c: char| c.is_alphanumeric() || "._%+-@".contains(c);
+        let mut redacted = String::with_capacity(text.len());
+        let mut found = false;
+        let mut rest = text;
+        while let Some(start) = rest.find(is_address_char) {
+            let (before, from_start) = rest.split_at(start);
+            let end = from_start
+                .find(|c: char| !is_address_char(c))
+                .unwrap_or(from_start.len());
+            let (word, after) = from_start.split_at(end);
+            redacted.push_str(before);
+            // e.g. the full stop at the end of a sentence
+            let address = word.trim_matches('.');
+            if address.contains('@') && address.validate_email() {
+                let leading = word.len() - word.trim_start_matches('.').len();
+                redacted.push_str(&word[..leading]);
+                redacted.push_str(&self.apply(address));
+                redacted.push_str(&word[leading + address.len()..]);
+                found = true;
+            } else {
+                redacted.push_str(word);
+            }
+            rest = after;
+        }
+        redacted.push_str(rest);
+        found.then_some(redacted)
+    }
+
+    /// Redacts, in place, the attributes whose key is one of the `PII_FIELDS`,
+    /// and the addresses quoted by the `ERROR_FIELDS`.
+    fn redact_attributes(&self, attributes: &mut [KeyValue]) {
+        if let Redaction::Off = self {
+            return;
+        }
+        for attribute in attributes.iter_mut() {
+            let key = attribute.key.as_str();
+            if PII_FIELDS.contains(&key) {
+                attribute.value = self.apply(&attribute.value.as_str()).into();
+            } else if ERROR_FIELDS.contains(&key)
+                && let Some(redacted) = self.redact_email_addresses(&attribute.value.as_str())
+            {
+                attribute.value = redacted.into();
+            }
+        }
+    }
+
+    /// `record` (a Bunyan JSON line), with its PII redacted: `None` if there is nothing to do.
+    fn redact_record(&self, record: &[u8]) -> Option<Vec<u8>> {
+        if let Redaction::Off = self {
+            return None;
+        }
+        let mut record: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(record).ok()?;
+        let mut redacted = false;
+        for field in PII_FIELDS {
+            if let Some(value) = record.get_mut(*field) {
+                let raw = match &*value {
+                    serde_json::Value::String(raw) => raw.clone(),
+                    other => other.to_string(),
+                };
+                *value = serde_json::Value::String(self.apply(&raw));
+                redacted = true;
+            }
+        }
+        for field in ERROR_FIELDS {
+            if let Some(serde_json::Value::String(text)) = record.get_mut(*field)
+                && let Some(scrubbed) = self.redact_email_addresses(text)
+            {
+                *text = scrubbed;
+                redacted = true;
+            }
+        }
+        if !redacted {
+            return None;
+        }
+        let mut line = serde_json::to_vec(&record).ok()?;
+        line.push(b'\n');
+        Some(line)
+    }
+}
+
+/// A sink that redacts the records it is handed, then passes them on to `Sink`.
+///
+/// # Implementation Notes
+///
+/// Redacting the formatted records, rather than the fields as they are recorded, catches
+/// them all: a span's fields are copied into its children's records, and into every event
+/// within it. `BunyanFormattingLayer` writes each record in a single `write_all`.
+// Spans exported over OTLP are redacted by `RedactingSpanProcessor` instead.
+pub struct Redacting<Sink> {
+    sink: Sink,
+    redaction: Redaction,
+}
+
+impl<Sink> Redacting<Sink> {
+    pub fn new(sink: Sink, redaction: Redaction) -> Self {
+        Self { sink, redaction }
+    }
+}
+
+impl<'a, Sink> MakeWriter<'a> for Redacting<Sink>
+where
+    Sink: MakeWriter<'a>,
+{
+    type Writer = RedactingWriter<'a, Sink::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            writer: self.sink.make_writer(),
+            redaction: &self.redaction,
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+        RedactingWriter {
+            writer: self.sink.make_writer_for(meta),
+            redaction: &self.redaction,
+        }
+    }
+}
+
+pub struct RedactingWriter<'a, W> {
+    writer: W,
+    redaction: &'a Redaction,
+}
+
+impl<W: Write> Write for RedactingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.redaction.redact_record(buf) {
+            Some(redacted) => self.writer.write_all(&redacted)?,
+            // Not a record (or nothing to redact): as is
+            None => self.writer.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Redacts the spans on their way to `Inner` (e.g. the batch exporter), as `Redacting`
+/// does the logs: the tracing backend is just as much a third party.
+///
+/// # Implementation Notes
+///
+/// `tracing_opentelemetry` turns span fields into span attributes, and event fields into
+/// attributes of the span's events: both are matched against `PII_FIELDS` (and `ERROR_FIELDS`)
+/// by key. Unlike the logs, fields are NOT copied from a span into its children here: there is
+/// nothing else to catch.
+pub struct RedactingSpanProcessor<Inner> {
+    inner: Inner,
+    redaction: Redaction,
+}
+
+impl<Inner> RedactingSpanProcessor<Inner> {
+    pub fn new(inner: Inner, redaction: Redaction) -> Self {
+        Self { inner, redaction }
+    }
+}
+
+// Not derived: the HMAC key is none of `Debug`'s business.
+impl<Inner: std::fmt::Debug> std::fmt::Debug for RedactingSpanProcessor<Inner> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedactingSpanProcessor")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Inner: SpanProcessor> SpanProcessor for RedactingSpanProcessor<Inner> {
+    fn on_start(&self, span: &mut opentelemetry_sdk::trace::Span, cx: &Context) {
+        self.inner.on_start(span, cx)
+    }
+
+    // Attributes are only final once the span has ended: redacted then.
+    fn on_end(&self, mut span: SpanData) {
+        self.redaction.redact_attributes(&mut span.attributes);
+        for event in span.events.events.iter_mut() {
+            self.redaction.redact_attributes(&mut event.attributes);
+        }
+        self.inner.on_end(span)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource)
+    }
+}
+
+/// Exports spans to `settings.otlp`, in batches, with their PII redacted as `redaction` says:
+/// `None` if there is nowhere to export to.
+///
+/// The caller must keep the provider, and `shutdown` it before exiting: whatever is still
+/// in the current batch would be lost otherwise.
+///
+/// # Implementation Notes
+///
+/// Must be called from within a tokio runtime: the gRPC client spawns its connection on it.
+// SCALA: like building an otel4s `Tracer` from a `Resource` - the provider being the resource
+pub fn build_tracer_provider(
+    settings: &TelemetrySettings,
+    redaction: Redaction,
+) -> Result<Option<SdkTracerProvider>, anyhow::Error> {
+    // W3C Trace Context (`traceparent`) in and out, whether we export or not
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let Some(otlp) = &settings.otlp else {
+        return Ok(None);
+    };
+    let exporter = match otlp.protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&otlp.endpoint)
+            .with_timeout(otlp.timeout())
+            .build(),
+        // NOTE: unlike gRPC, the path is ours to add
+        OtlpProtocol::Http => opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(format!("{}/v1/traces", otlp.endpoint.trim_end_matches('/')))
+            .with_timeout(otlp.timeout())
+            .build(),
+    }
+    .context("Failed to build the OTLP span exporter")?;
+
+    let resource_attributes = settings
+        .resource_attributes()
+        .map_err(anyhow::Error::msg)?
+        .into_iter()
+        .map(|(key, value)| KeyValue::new(key, value));
+    let resource = Resource::builder()
+        .with_service_name(settings.service_name.clone())
+        .with_attributes(resource_attributes)
+        .build();
+
+    Ok(Some(
+        SdkTracerProvider::builder()
+            .with_span_processor(RedactingSpanProcessor::new(
+                BatchSpanProcessor::builder(exporter).build(),
+                redaction,
+            ))
+            // The caller's decision, if it made one: no half-exported traces
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                settings.sampling_ratio,
+            ))))
+            .with_resource(resource)
+            .build(),
+    ))
+}
+
+/// What `get_subscriber` expects from a provider.
+pub fn tracer(provider: &SdkTracerProvider) -> SdkTracer {
+    provider.tracer(env!("CARGO_PKG_NAME"))
+}
+
+/// The trace context of the current span, as a `traceparent` header value.
+/// `None` when there is none to propagate (e.g. nothing is exported).
+pub fn current_traceparent() -> Option<String> {
+    let mut carrier = HashMap::new();
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut carrier)
+    });
+    carrier.remove("traceparent")
+}
+
+/// Makes `span` part of the trace `traceparent` belongs to: must be called before `span`
+/// is first entered. A malformed `traceparent` is ignored.
+pub fn continue_trace(span: &Span, traceparent: &str) {
+    let carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    let parent =
+        opentelemetry::global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    // Fails if nothing is exported (no OpenTelemetry layer): nothing to continue then
+    let _ = span.set_parent(parent);
+}
+
+/// Lets the propagator read W3C Trace Context headers straight from an actix request.
+struct ActixHeaders<'a>(&'a header::HeaderMap);
+
+impl Extractor for ActixHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+fn remote_parent(headers: &header::HeaderMap) -> Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&ActixHeaders(headers))
+    })
 }
 
 /// Register a subscriber as global default to process span data.
@@ -44,3 +453,104 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // specify which subscriber should process the span
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+/// `tokio::task::spawn_blocking`, but the closure runs INSIDE the caller's current span.
+///
+/// # Implementation Notes
+///
+/// Spans are attached to the current thread: a closure shipped off to tokio's
+/// blocking thread pool would otherwise lose its context, and whatever it logs
+/// could no longer be correlated with the request that triggered it.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
+
+/// The route template `request` matches (e.g. `/admin/password`), not its actual path:
+/// a bounded set of values, to group by. `default` if it matches none.
+pub fn route_template(request: &HttpRequest) -> String {
+    request
+        .match_pattern()
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// The root span of every request, for `tracing_actix_web::TracingLogger`: what came in,
+/// what went out, how long it took, and on whose behalf.
+///
+/// # Implementation Notes
+///
+/// Not `tracing_actix_web::root_span!`: it records ITS OWN request id (always a new one),
+/// whereas ours may come from the caller (see `request_id`).
+// SCALA: like an http4s `Logger` middleware, with a MDC filled from the request
+pub struct RequestSpanBuilder;
+
+/// When the request came in, for `RequestSpanBuilder::on_request_end` to compute the latency.
+struct RequestStart(Instant);
+
+impl RootSpanBuilder for RequestSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        request
+            .extensions_mut()
+            .insert(RequestStart(Instant::now()));
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            // Only if `assign_request_id` isn't registered (outside of `TracingLogger`)
+            .unwrap_or_else(|| RequestId::from_headers(request.headers()));
+        let trace_id = request
+            .headers()
+            .get("traceparent")
+            .and_then(|value| value.to_str().ok())
+            .and_then(TraceParent::parse)
+            .map(|traceparent| traceparent.trace_id);
+        let route = route_template(request.request());
+        let span = tracing::info_span!(
+            "HTTP request",
+            %request_id,
+            trace_id = trace_id.as_deref().map(tracing::field::display),
+            http.method = %request.method(),
+            http.route = %route,
+            http.target = %request.uri(),
+            http.user_agent = request
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or(""),
+            http.status_code = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+            user_id = tracing::field::Empty,
+            // For the tracing backend, if any: e.g. `GET /admin/password`
+            otel.name = %format!("{} {}", request.method(), route),
+            otel.kind = "server",
+        );
+        // A child of the caller's span, if it sent a `traceparent`: one trace across services
+        // NOTE: fails (harmlessly) when nothing is exported
+        let _ = span.set_parent(remote_parent(request.headers()));
+        span
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        let status = match outcome {
+            Ok(response) => {
+                let request = response.request();
+                if let Some(RequestStart(start)) = request.extensions().get::<RequestStart>() {
+                    span.record("latency_ms", start.elapsed().as_millis() as u64);
+                }
+                // Set by whoever authenticated the request (e.g. `reject_anonymous_users`)
+                if let Some(user_id) = request.extensions().get::<UserId>() {
+                    span.record("user_id", tracing::field::display(user_id));
+                }
+                response.status()
+            }
+            // NOTE: no request to look into anymore, hence no latency nor user id:
+            // `problem::render_problems` only lets errors through for the middlewares outside of it.
+            Err(e) => e.as_response_error().status_code(),
+        };
+        span.record("http.status_code", status.as_u16());
+    }
+}
@@ -0,0 +1,83 @@
+//! tests/api/admin_dashboard.rs
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.get_admin_dashboard().await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_log_out() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.post_logout().await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logout_clears_session_state() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let response = app.login_as_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // ACT - Part 1 - Logout
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(
+        html_page.contains(r#"<p class="flash-info"><i>You have successfully logged out.</i></p>"#)
+    );
+
+    // ACT - Part 2 - Attempt to load the admin dashboard
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // ASSERT
+    let stored = sqlx::query!(r#"SELECT count(*) AS "count!" FROM sessions"#)
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.count, 0);
+}
+
+#[tokio::test]
+async fn the_username_is_escaped_on_the_dashboard() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let username = r#"<script>alert("pwned")</script>"#;
+    sqlx::query!(
+        "UPDATE users SET username = $1 WHERE user_id = $2",
+        username,
+        app.test_user.user_id,
+    )
+    .execute(&app.db_conn_pool)
+    .await
+    .unwrap();
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // ACT
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // ASSERT
+    assert!(!html_page.contains(username), "{}", html_page);
+    assert!(html_page.contains("Welcome &lt;script&gt;alert(&quot;pwned&quot;)&lt;/script&gt;!"));
+}
@@ -0,0 +1,262 @@
+//! tests/api/change_password.rs
+
+use uuid::Uuid;
+use zero2prod::configuration::PasswordHashingSettings;
+use zero2prod::credentials::compute_password_hash;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+
+fn change_password_body(current: &str, new: &str, check: &str) -> serde_json::Value {
+    serde_json::json!({
+        "current_password": current,
+        "new_password": new,
+        "new_password_check": check,
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_change_password_form() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.get_change_password().await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_your_password() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // ACT
+    let response = app
+        .post_change_password(&change_password_body(
+            &Uuid::new_v4().to_string(),
+            &new_password,
+            &new_password,
+        ))
+        .await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn new_password_fields_must_match() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    // ACT
+    let response = app
+        .post_change_password(&change_password_body(
+            &app.test_user.password,
+            &Uuid::new_v4().to_string(),
+            &Uuid::new_v4().to_string(),
+        ))
+        .await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(
+        "<p class=\"flash-error\"><i>You entered two different new passwords - \
+        the field values must match.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn current_password_must_be_valid() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+    app.login_as_test_user().await;
+
+    // ACT
+    let response = app
+        .post_change_password(&change_password_body(
+            &Uuid::new_v4().to_string(),
+            &new_password,
+            &new_password,
+        ))
+        .await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(
+        html_page
+            .contains("<p class=\"flash-error\"><i>The current password is incorrect.</i></p>")
+    );
+}
+
+#[tokio::test]
+async fn new_passwords_outside_the_length_bounds_are_rejected() {
+    // ARRANGE
+    let app = spawn_app_with(|config| {
+        config.password.policy.min_length = 12;
+        config.password.policy.max_length = 128;
+    })
+    .await;
+    app.login_as_test_user().await;
+    let test_cases = vec![
+        ("a".repeat(11), "at least 12 characters"),
+        ("a".repeat(129), "at most 128 characters"),
+    ];
+
+    for (new_password, expected_reason) in test_cases {
+        // ACT
+        let response = app
+            .post_change_password(&change_password_body(
+                &app.test_user.password,
+                &new_password,
+                &new_password,
+            ))
+            .await;
+
+        // ASSERT
+        assert_is_redirect_to(&response, "/admin/password");
+        let html_page = app.get_change_password_html().await;
+        assert!(
+            html_page.contains(expected_reason),
+            "A {}-character password was not rejected",
+            new_password.len()
+        );
+    }
+    // The old password still works
+    app.post_logout().await;
+    let response = app.login_as_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn breached_passwords_are_rejected() {
+    // ARRANGE
+    let breached_list = std::env::temp_dir().join(format!("breached-{}.txt", Uuid::new_v4()));
+    std::fs::write(
+        &breached_list,
+        "123456\ncorrect horse battery staple\npassword1\n",
+    )
+    .unwrap();
+    let app = spawn_app_with(|config| {
+        config.password.policy.breached_passwords_path =
+            Some(breached_list.to_string_lossy().into_owned());
+    })
+    .await;
+    app.login_as_test_user().await;
+
+    // ACT
+    let response = app
+        .post_change_password(&change_password_body(
+            &app.test_user.password,
+            "correct horse battery staple",
+            "correct horse battery staple",
+        ))
+        .await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("has appeared in a data breach"));
+    std::fs::remove_file(breached_list).unwrap();
+}
+
+#[tokio::test]
+async fn changing_password_works() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // ACT - Part 1 - Login
+    let response = app.login_as_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // ACT - Part 2 - Change password
+    let response = app
+        .post_change_password(&change_password_body(
+            &app.test_user.password,
+            &new_password,
+            &new_password,
+        ))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    // ACT - Part 3 - Follow the redirect
+    let html_page = app.get_change_password_html().await;
+    assert!(
+        html_page.contains("<p class=\"flash-info\"><i>Your password has been changed.</i></p>")
+    );
+
+    // ACT - Part 4 - Logout, and the old password is no good anymore...
+    app.post_logout().await;
+    let response = app.login_as_test_user().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // ... but the new one is
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_on_login() {
+    // ARRANGE
+    let app = spawn_app_with(|config| {
+        config.password.hashing = PasswordHashingSettings {
+            memory_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+        };
+    })
+    .await;
+    // As if the test user had set their password before the parameters were changed
+    let outdated = PasswordHashingSettings {
+        memory_kib: 8192,
+        iterations: 1,
+        parallelism: 1,
+    };
+    let outdated_hash = compute_password_hash(&app.test_user.password, &outdated).unwrap();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        outdated_hash,
+        app.test_user.user_id
+    )
+    .execute(&app.db_conn_pool)
+    .await
+    .unwrap();
+
+    // ACT
+    let response = app.login_as_test_user().await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let upgraded_hash = app.stored_password_hash().await;
+    assert!(upgraded_hash.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+    // Still the same password
+    app.post_logout().await;
+    let response = app.login_as_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn up_to_date_password_hashes_are_left_alone_on_login() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let stored_hash = app.stored_password_hash().await;
+
+    // ACT
+    let response = app.login_as_test_user().await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    // Same salt: it was not recomputed
+    assert_eq!(app.stored_password_hash().await, stored_hash);
+}
@@ -0,0 +1,121 @@
+//! tests/api/flash_messages.rs
+//! Flash messages live in a cookie: clients must not be able to forge or alter them.
+
+use reqwest::header::{COOKIE, SET_COOKIE};
+
+use crate::helpers::{TestApp, spawn_app};
+
+const FLASH_COOKIE: &str = "_flash";
+
+/// Fails a login and hands back the (signed) flash cookie the app set on the redirect.
+async fn failed_login_flash_cookie(app: &TestApp) -> String {
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    let response = app.post_login(&login_body).await;
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .filter_map(|h| h.split(';').next())
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == FLASH_COOKIE)
+        .map(|(_, value)| value.to_string())
+        .expect("No flash cookie was set on the redirect")
+}
+
+/// GET /login with a hand-picked flash cookie, from a client with no cookie jar.
+async fn get_login_with_flash_cookie(app: &TestApp, value: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/login", &app.root_address))
+        .header(COOKIE, format!("{}={}", FLASH_COOKIE, value))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn an_untouched_flash_cookie_is_displayed() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let cookie = failed_login_flash_cookie(&app).await;
+
+    // ACT
+    let response = get_login_with_flash_cookie(&app, &cookie).await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("Authentication failed.")
+    );
+}
+
+#[tokio::test]
+async fn a_tampered_flash_cookie_is_rejected() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let cookie = failed_login_flash_cookie(&app).await;
+    // Same signature, different content
+    let tampered = cookie.replace("Authentication", "Authorization");
+    assert_ne!(
+        tampered, cookie,
+        "The cookie does not carry the message in clear"
+    );
+
+    // ACT
+    let response = get_login_with_flash_cookie(&app, &tampered).await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(
+        !response
+            .text()
+            .await
+            .unwrap()
+            .contains("Authorization failed.")
+    );
+}
+
+#[tokio::test]
+async fn a_forged_flash_cookie_is_rejected() {
+    // ARRANGE
+    let app = spawn_app().await;
+    // Well-formed messages, but signed with a key the app has never heard of
+    let forged = "bm90LWEtcmVhbC1zaWduYXR1cmU%3D%5B%7B%22content%22%3A%22Forged%20message%22%2C%22level%22%3A%22Info%22%7D%5D";
+
+    // ACT
+    let response = get_login_with_flash_cookie(&app, forged).await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!response.text().await.unwrap().contains("Forged message"));
+}
+
+#[tokio::test]
+async fn a_rejected_flash_cookie_is_cleared() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let cookie = failed_login_flash_cookie(&app).await;
+    let tampered = cookie.replace("Authentication", "Authorization");
+
+    // ACT
+    let response = get_login_with_flash_cookie(&app, &tampered).await;
+
+    // ASSERT
+    // The browser is told to drop it: no user is stuck on an error page
+    let removal = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .find(|h| h.starts_with(&format!("{}=", FLASH_COOKIE)))
+        .expect("The flash cookie was not cleared");
+    assert!(removal.starts_with(&format!("{}=;", FLASH_COOKIE)));
+    assert!(removal.contains("Max-Age=0"));
+}
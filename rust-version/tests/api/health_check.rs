@@ -0,0 +1,119 @@
+//! tests/api/health_check.rs
+
+use std::time::Duration;
+
+use wiremock::matchers::method;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn liveness_only_checks_the_process() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.get_health("live").await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn readiness_reports_every_check_with_its_latency() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.get_health("ready").await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["status"], "ready");
+    let checks = report["checks"].as_array().unwrap();
+    let names: Vec<&str> = checks.iter().map(|c| c["name"].as_str().unwrap()).collect();
+    // The email backend is not checked unless asked to
+    assert_eq!(names, vec!["database", "migrations"]);
+    for check in checks {
+        assert_eq!(check["status"], "up");
+        assert_eq!(check["required"], true);
+        assert!(check["latency_ms"].as_f64().unwrap() >= 0.0);
+    }
+}
+
+#[tokio::test]
+async fn readiness_fails_when_migrations_are_pending() {
+    // ARRANGE
+    let app = spawn_app().await;
+    // As if the latest migration had not been run against this database
+    sqlx::query!(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT max(version) FROM _sqlx_migrations)"
+    )
+    .execute(&app.db_conn_pool)
+    .await
+    .unwrap();
+
+    // ACT
+    let response = app.get_health("ready").await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 503);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["status"], "not_ready");
+    let migrations = &report["checks"][1];
+    assert_eq!(migrations["name"], "migrations");
+    assert_eq!(migrations["status"], "down");
+    assert!(
+        migrations["error"]
+            .as_str()
+            .unwrap()
+            .contains("Pending migrations")
+    );
+}
+
+#[tokio::test]
+async fn readiness_can_ping_the_email_backend() {
+    // ARRANGE
+    let app = spawn_app_with(|config| config.health.check_email_backend = true).await;
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let response = app.get_health("ready").await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    let email_backend = &report["checks"][2];
+    assert_eq!(email_backend["name"], "email_backend");
+    assert_eq!(email_backend["status"], "up");
+}
+
+#[tokio::test]
+async fn a_slow_email_backend_does_not_make_the_application_unready() {
+    // ARRANGE
+    let app = spawn_app_with(|config| {
+        config.health.check_email_backend = true;
+        config.health.email_backend_timeout_milliseconds = 100;
+    })
+    .await;
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let response = app.get_health("ready").await;
+
+    // ASSERT
+    // Deliveries are queued: an email outage only delays them
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    let email_backend = &report["checks"][2];
+    assert_eq!(email_backend["status"], "down");
+    assert_eq!(email_backend["required"], false);
+}
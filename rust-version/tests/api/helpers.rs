@@ -0,0 +1,549 @@
+//! tests/api/helpers.rs
+//! Shared test harness: every test module spins up its own app (and its own database) through here.
+
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use secrecy::SecretString;
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{
+    DBUser, DatabaseSettings, EmailTransportSettings, IdempotencySettings, IssueDeliverySettings,
+    PasswordHashingSettings, Settings, get_configuration,
+};
+use zero2prod::credentials::compute_password_hash;
+use zero2prod::email_client::EmailClient;
+use zero2prod::issue_delivery_worker::{ExecutionOutcome, try_execute_task};
+use zero2prod::metrics::Metrics;
+use zero2prod::shutdown::Shutdown;
+use zero2prod::startup::{Application, get_connection_pool};
+use zero2prod::telemetry::{Redaction, get_subscriber, init_subscriber};
+
+// Ensure that the `tracing` stack is only initialised once:
+// a global subscriber can only be set once per process, and every test spawns an app.
+// Silent by default; `TEST_LOG=true cargo test` to see what the application logs
+// (e.g. `TEST_LOG=true cargo test failing_test | bunyan`).
+// SCALA: a `lazy val`, forced by the first test to touch it
+static TRACING: LazyLock<()> = LazyLock::new(|| {
+    let default_filter_level = "info".to_string();
+    let subscriber_name = "test".to_string();
+    // NOTE: two branches, two types: `get_subscriber`'s return type depends on its sink.
+    if std::env::var("TEST_LOG").is_ok() {
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            Redaction::Off,
+            None,
+        );
+        init_subscriber(subscriber);
+    } else {
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            Redaction::Off,
+            None,
+        );
+        init_subscriber(subscriber);
+    }
+});
+
+/// The links of a confirmation email: one per body.
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+/// A user who is allowed to publish, with a random username and password.
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    pub async fn store(&self, db_conn_pool: &PgPool, hashing: &PasswordHashingSettings) {
+        // Same hashing code as the application's: no hand-crafted PHC strings.
+        let password_hash = compute_password_hash(&self.password, hashing)
+            .expect("Failed to hash the test password");
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(db_conn_pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
+pub struct TestApp {
+    pub root_address: String,
+    pub port: u16,
+    // Where `/metrics` is served, when the admin listener is enabled (`server.admin_port`)
+    pub admin_address: Option<String>,
+    pub db_conn_pool: PgPool,
+    // Stands in for the email API: tests program it with the emails they expect to be sent.
+    pub email_server: MockServer,
+    // What a delivery worker would be running with, so that tests can drain the queue on demand
+    pub email_client: EmailClient,
+    pub issue_delivery: IssueDeliverySettings,
+    // The application's own: what tests deliver on its behalf gets counted
+    pub metrics: Metrics,
+    pub idempotency: IdempotencySettings,
+    // Triggering it is what a SIGTERM would do to the real thing (minus stopping the server)
+    pub shutdown: Shutdown,
+    pub test_user: TestUser,
+    // Keeps cookies between requests (i.e. a browser session), and does NOT follow
+    // redirects, so that tests can assert on them.
+    pub api_client: reqwest::Client,
+}
+
+impl TestApp {
+    pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscription", &self.root_address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_confirm(&self, query: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/subscriptions/confirm{}",
+                &self.root_address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.root_address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.root_address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `GET /health/{probe}`
+    pub async fn get_health(&self, probe: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/health/{}", &self.root_address, probe))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Subscribes `email`, leaving it pending confirmation: returns the links it was emailed.
+    pub async fn create_unconfirmed_subscriber(&self, email: &str) -> ConfirmationLinks {
+        let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+        // SCOPED: gone when the guard is, so that the test's own mocks only see ITS emails
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create unconfirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .expect("Failed to create an unconfirmed subscriber");
+
+        let email_request = self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .expect("No confirmation email was sent");
+        self.get_confirmation_links(&email_request)
+    }
+
+    /// Subscribes `email` and clicks on its confirmation link.
+    pub async fn create_confirmed_subscriber(&self, email: &str) {
+        let confirmation_links = self.create_unconfirmed_subscriber(email).await;
+        reqwest::get(confirmation_links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .expect("Failed to confirm the subscriber");
+    }
+
+    /// The confirmation links in an email sent to the email API, pointing at this app.
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let get_link = |s: &str| {
+            let links: Vec<&str> = s
+                .split(|c: char| c.is_whitespace() || c == '"')
+                .filter(|word| word.starts_with("http"))
+                .collect();
+            assert_eq!(links.len(), 1, "Expected one link in {}", s);
+            let mut confirmation_link = reqwest::Url::parse(links[0]).unwrap();
+            // Never a link to some random site on the web!
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            // `application.base_url` knows nothing of the port we were given
+            confirmation_link.set_port(Some(self.port)).unwrap();
+            confirmation_link
+        };
+        ConfirmationLinks {
+            html: get_link(body["HtmlBody"].as_str().unwrap()),
+            plain_text: get_link(body["TextBody"].as_str().unwrap()),
+        }
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.root_address))
+            // `.form` url-encodes the body and sets the `Content-Type` header accordingly
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Logs in as the test user.
+    pub async fn login_as_test_user(&self) -> reqwest::Response {
+        self.post_login(&serde_json::json!({
+            "username": &self.test_user.username,
+            "password": &self.test_user.password
+        }))
+        .await
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.root_address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.root_address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.root_address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.root_address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// The PHC string currently stored for the test user.
+    pub async fn stored_password_hash(&self) -> String {
+        sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            self.test_user.user_id
+        )
+        .fetch_one(&self.db_conn_pool)
+        .await
+        .expect("Failed to fetch the test user's password hash")
+        .password_hash
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.root_address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Plays the part of the background worker: drains the delivery queue
+    /// (well, all the tasks that are already due).
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_conn_pool,
+                &self.email_client,
+                &self.issue_delivery,
+                &self.metrics,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+}
+
+// No .await call, therefore no need for `spawn_app` to be async now.
+// We are also running tests, so it is not worth it to propagate errors:
+// if we fail to perform the required setup we can just panic and crash.
+pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as `spawn_app`, with a chance to tweak the configuration first.
+pub async fn spawn_app_with(customize: impl FnOnce(&mut Settings)) -> TestApp {
+    // Only the first test to get here initialises it: the others go straight through
+    LazyLock::force(&TRACING);
+
+    // WARNING: In order to achieve 'test isolation' & determinism
+    // Before each test run, we want to:
+    //  - create a new db with a random, unique name
+    //  - run database migration
+    let mut config: Settings = get_configuration().expect("Failed to read config");
+    config.database.name = Uuid::new_v4().to_string();
+    // Any free port: several applications run side by side
+    config.server.port = 0;
+    // Tests drain the delivery queue on demand (see `dispatch_all_pending_emails`)
+    config.issue_delivery.workers = 0;
+    customize(&mut config);
+    let db_conn_pool = configure_database(&config.database).await;
+    // The port is only known once bound: see `get_confirmation_links`
+    config.application.base_url = "http://127.0.0.1".into();
+
+    // Whatever the configuration says, emails go to our mock server.
+    let email_server = MockServer::start().await;
+    config.email_client.transport = EmailTransportSettings::Http {
+        base_url: email_server.uri(),
+        authorization_token: SecretString::from("my-secret-token"),
+    };
+
+    // What a delivery worker would be running with: tests drain the queue themselves
+    let worker_email_client = config
+        .email_client
+        .clone()
+        .client()
+        .expect("Invalid email client configuration.");
+
+    let application = Application::build(config.clone())
+        .await
+        .expect("Failed to build the application");
+    let port = application.port();
+    let admin_port = application.admin_port();
+    let metrics = application.metrics();
+    let shutdown = application.shutdown_handle();
+    // Launch the application as a background task
+    // tokio::spawn returns a handle to the spawned future,
+    // but we have no use for it here, hence we explicitly drop it
+    drop(tokio::spawn(application.run_until_stopped()));
+
+    let test_user = TestUser::generate();
+    test_user
+        .store(&db_conn_pool, &config.password.hashing)
+        .await;
+
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    TestApp {
+        root_address: format!("http://127.0.0.1:{}", port),
+        port,
+        admin_address: admin_port.map(|admin_port| format!("http://127.0.0.1:{}", admin_port)),
+        db_conn_pool,
+        email_server,
+        email_client: worker_email_client,
+        issue_delivery: config.issue_delivery,
+        metrics,
+        idempotency: config.idempotency,
+        shutdown,
+        test_user,
+        api_client,
+    }
+}
+
+pub async fn configure_database(db_conf: &DatabaseSettings) -> PgPool {
+    let maintenant_db_conf = DatabaseSettings {
+        name: "postgres".to_string(),
+        user: DBUser {
+            name: "postgres".to_string(),
+            password: SecretString::from("password"),
+        },
+        // CLAUDE: to comment ... i do understand we're 'copying' everything else form the
+        // db_conf.clone()... but what's the proper term for what is done / this syntax ?
+        ..db_conf.clone()
+    };
+
+    let mut db_conn = PgConnection::connect_with(&maintenant_db_conf.with_db())
+        .await
+        .expect("Failed to connect to maintenance postgres instance");
+
+    db_conn
+        // CLAUDE: please explain this r#""# syntax
+        .execute(format!(r#"CREATE DATABASE "{}";"#, db_conf.name).as_str())
+        .await
+        .expect("Failed to create test db");
+
+    let db_conn_pool = get_connection_pool(db_conf);
+
+    sqlx::migrate!("./migrations")
+        .run(&db_conn_pool)
+        .await
+        .expect("Failed to migrate test db");
+
+    db_conn_pool
+}
+
+/// The actual binary, as deployed, in its own process: signals are process-wide.
+pub struct SpawnedBinary {
+    child: Child,
+    pub address: String,
+    pub db_conn_pool: PgPool,
+}
+
+impl SpawnedBinary {
+    pub fn terminate(&self) {
+        let status = Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .expect("Failed to run `kill`");
+        assert!(status.success());
+    }
+
+    /// Waits for the process to exit, returning whether it did so successfully, and what it logged.
+    pub async fn wait(self, timeout: Duration) -> (bool, String) {
+        let output = tokio::time::timeout(
+            timeout,
+            tokio::task::spawn_blocking(move || self.child.wait_with_output()),
+        )
+        .await
+        .expect("The application did not exit in time")
+        .unwrap()
+        .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        )
+    }
+}
+
+/// `env`: on top of what any test needs, e.g. `[("APP_TELEMETRY__SERVICE_NAME", "...")]`.
+pub async fn spawn_binary(email_server: &MockServer, env: &[(&str, &str)]) -> SpawnedBinary {
+    let mut config = get_configuration().expect("Failed to read config");
+    config.database.name = Uuid::new_v4().to_string();
+    let db_conn_pool = configure_database(&config.database).await;
+    // A free port, handed over to the child process
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_zero2prod"))
+        .env("RUST_LOG", "info")
+        .env("APP_DATABASE__NAME", &config.database.name)
+        .env("APP_SERVER__PORT", port.to_string())
+        .env(
+            "APP_APPLICATION__BASE_URL",
+            format!("http://127.0.0.1:{}", port),
+        )
+        .env("APP_EMAIL_CLIENT__BASE_URL", email_server.uri())
+        .env("APP_HEALTH__CHECK_EMAIL_BACKEND", "true")
+        .env("APP_SHUTDOWN__GRACE_PERIOD_SECONDS", "10")
+        .envs(env.iter().copied())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to spawn the application");
+    let app = SpawnedBinary {
+        child,
+        address: format!("http://127.0.0.1:{}", port),
+        db_conn_pool,
+    };
+
+    // Up once it answers
+    let started = Instant::now();
+    while reqwest::get(format!("{}/health/live", app.address))
+        .await
+        .is_err()
+    {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "The application did not start"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    app
+}
+
+/// The response is a redirect (303 See Other) to `location`.
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
+/// The response is an RFC 7807 problem with `status`: returns it, for further assertions.
+pub async fn assert_is_problem(response: reqwest::Response, status: u16) -> serde_json::Value {
+    assert_eq!(response.status().as_u16(), status);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], status);
+    problem
+}
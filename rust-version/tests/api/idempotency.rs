@@ -0,0 +1,234 @@
+//! tests/api/idempotency.rs
+//! Publishing twice with the same idempotency key sends the issue once.
+
+use std::time::Duration;
+
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::idempotency::delete_expired_idempotency_keys;
+
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn newsletter_publishing_is_idempotent() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // ACT - Part 1 - Publish
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let first_body = response.text().await.unwrap();
+
+    // ACT - Part 2 - Publish AGAIN
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key)
+        .await;
+
+    // ASSERT
+    // Same response...
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "application/json"
+    );
+    assert_eq!(response.text().await.unwrap(), first_body);
+    // ... but only one issue, and one email
+    assert_eq!(count_issues(&app).await, 1);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_idempotency_key_can_be_sent_as_a_form_field() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let mut body = newsletter_request_body();
+    body["idempotency_key"] = Uuid::new_v4().to_string().into();
+
+    // ACT
+    let first = app.post_newsletters(body.clone()).await;
+    let second = app.post_newsletters(body).await;
+
+    // ASSERT
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn different_idempotency_keys_publish_different_issues() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    for _ in 0..2 {
+        let response = app
+            .post_newsletters_with_idempotency_key(
+                newsletter_request_body(),
+                &Uuid::new_v4().to_string(),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+
+    // ASSERT
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn invalid_idempotency_keys_are_rejected_with_a_400() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![("".to_string(), "empty"), ("a".repeat(50), "too long")];
+
+    for (idempotency_key, description) in test_cases {
+        // ACT
+        let response = app
+            .post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key)
+            .await;
+
+        // ASSERT
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject an idempotency key that was {}",
+            description
+        );
+    }
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn concurrent_form_submission_is_handled_gracefully() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // ACT - Submit two forms concurrently
+    let response1 =
+        app.post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key);
+    let response2 =
+        app.post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    // ASSERT
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    assert_eq!(count_issues(&app).await, 1);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_duplicate_that_waits_too_long_gets_a_409() {
+    // ARRANGE
+    let app = spawn_app_with(|config| config.idempotency.lock_timeout_milliseconds = 100).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    // Plays the part of a first request which is taking its time
+    let mut in_flight = app.db_conn_pool.begin().await.unwrap();
+    sqlx::query!(
+        "INSERT INTO idempotency (user_id, idempotency_key) VALUES ($1, $2)",
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&mut *in_flight)
+    .await
+    .unwrap();
+
+    // ACT
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key)
+        .await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(count_issues(&app).await, 0);
+
+    // Once the first request gives up, the key is up for grabs again
+    in_flight.rollback().await.unwrap();
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn expired_idempotency_keys_are_cleaned_up() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let expired_key = Uuid::new_v4().to_string();
+    let fresh_key = Uuid::new_v4().to_string();
+    for key in [&expired_key, &fresh_key] {
+        app.post_newsletters_with_idempotency_key(newsletter_request_body(), key)
+            .await;
+    }
+    let age = app.idempotency.ttl() + Duration::from_secs(60);
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET created_at = now() - make_interval(secs => $1)
+        WHERE idempotency_key = $2
+        "#,
+        age.as_secs_f64(),
+        expired_key
+    )
+    .execute(&app.db_conn_pool)
+    .await
+    .unwrap();
+
+    // ACT
+    let deleted = delete_expired_idempotency_keys(&app.db_conn_pool, &app.idempotency)
+        .await
+        .unwrap();
+
+    // ASSERT
+    assert_eq!(deleted, 1);
+    let remaining: Vec<String> = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_conn_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.idempotency_key)
+        .collect();
+    assert_eq!(remaining, vec![fresh_key]);
+}
@@ -0,0 +1,162 @@
+//! tests/api/issue_delivery.rs
+
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::{ExecutionOutcome, try_execute_task};
+
+use crate::helpers::{TestApp, spawn_app};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+struct QueuedTask {
+    status: String,
+    n_retries: i32,
+    is_due: bool,
+}
+
+async fn queued_task(app: &TestApp) -> QueuedTask {
+    sqlx::query_as!(
+        QueuedTask,
+        r#"
+        SELECT status, n_retries, execute_after <= now() AS "is_due!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_conn_pool)
+    .await
+    .expect("Failed to fetch the queued task")
+}
+
+async fn try_execute_task_once(app: &TestApp) -> ExecutionOutcome {
+    try_execute_task(
+        &app.db_conn_pool,
+        &app.email_client,
+        &app.issue_delivery,
+        &app.metrics,
+    )
+    .await
+    .expect("Failed to execute task")
+}
+
+#[tokio::test]
+async fn delivered_tasks_are_removed_from_the_queue() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let first = try_execute_task_once(&app).await;
+    let second = try_execute_task_once(&app).await;
+
+    // ASSERT
+    assert!(matches!(first, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(second, ExecutionOutcome::EmptyQueue));
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_later() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let first = try_execute_task_once(&app).await;
+    // The task is backing off: it is not due yet
+    let second = try_execute_task_once(&app).await;
+
+    // ASSERT
+    assert!(matches!(first, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(second, ExecutionOutcome::EmptyQueue));
+    let task = queued_task(&app).await;
+    assert_eq!(task.status, "pending");
+    assert_eq!(task.n_retries, 1);
+    assert!(!task.is_due);
+}
+
+#[tokio::test]
+async fn tasks_are_dead_lettered_once_they_run_out_of_retries() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+    let max_attempts = app.issue_delivery.max_retries as u64 + 1;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(max_attempts)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    for _ in 0..max_attempts {
+        try_execute_task_once(&app).await;
+        // Fast-forward through the backoff
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&app.db_conn_pool)
+            .await
+            .unwrap();
+    }
+    let outcome = try_execute_task_once(&app).await;
+
+    // ASSERT
+    // Dead letters are kept around (for inspection), but never picked up again
+    assert!(matches!(outcome, ExecutionOutcome::EmptyQueue));
+    let task = queued_task(&app).await;
+    assert_eq!(task.status, "dead_letter");
+    assert_eq!(task.n_retries as u32, app.issue_delivery.max_retries);
+}
+
+#[tokio::test]
+async fn concurrent_workers_deliver_each_email_exactly_once() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let n_subscribers = 10;
+    for i in 0..n_subscribers {
+        app.create_confirmed_subscriber(&format!("reader{}@gmail.com", i))
+            .await;
+    }
+    app.post_newsletters(newsletter_request_body()).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        // Slow enough for the workers to actually overlap
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(20)))
+        .expect(n_subscribers)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    // Two workers, draining the same queue at the same time
+    tokio::join!(
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails()
+    );
+
+    // ASSERT: the mock server verifies, on drop, that each email was sent exactly once
+}
@@ -0,0 +1,182 @@
+//! tests/api/login.rs
+
+use std::collections::HashMap;
+
+use actix_session::storage::SessionStore;
+use actix_web::cookie::time::Duration;
+use uuid::Uuid;
+use zero2prod::session_store::{InMemorySessionStore, SessionStoreKind, delete_expired_sessions};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn the_login_form_is_served() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let html_page = app.get_login_html().await;
+
+    // ASSERT
+    assert!(html_page.contains(r#"<form action="/login" method="post">"#));
+}
+
+#[tokio::test]
+async fn invalid_credentials_redirect_back_to_the_login_form() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+
+    // ACT
+    let response = app.post_login(&login_body).await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/login");
+    // The error is displayed once, on the page we are redirected to...
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"<p class="flash-error"><i>Authentication failed.</i></p>"#));
+    // ... and is gone on reload
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed."));
+    // ... and no way into the admin area
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT - Part 1 - Login
+    let response = app.login_as_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // ACT - Part 2 - Follow the redirect
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_session_key_is_rotated_on_login() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+    let first_key = sqlx::query!("SELECT session_key FROM sessions")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("No session was stored on login")
+        .session_key;
+
+    // ACT
+    // Logging in again, from the same browser
+    app.login_as_test_user().await;
+
+    // ASSERT
+    let keys: Vec<String> = sqlx::query!("SELECT session_key FROM sessions")
+        .fetch_all(&app.db_conn_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.session_key)
+        .collect();
+    // The old key has been discarded, server-side, and replaced by a new one
+    assert_eq!(keys.len(), 1);
+    assert_ne!(keys[0], first_key);
+    assert!(app.get_admin_dashboard().await.status().is_success());
+}
+
+#[tokio::test]
+async fn login_works_with_the_in_memory_session_store() {
+    // ARRANGE
+    let app = spawn_app_with(|config| config.session.store = SessionStoreKind::Memory).await;
+
+    // ACT
+    let response = app.login_as_test_user().await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+    // Nothing went to the database
+    let stored = sqlx::query!(r#"SELECT count(*) AS "count!" FROM sessions"#)
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.count, 0);
+}
+
+#[tokio::test]
+async fn a_wrong_password_for_an_existing_user_is_rejected() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": Uuid::new_v4().to_string()
+    });
+
+    // ACT
+    let response = app.post_login(&login_body).await;
+
+    // ASSERT
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn expired_sessions_are_purged() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (session_key, state, expires_at)
+        VALUES ('expired', '{}', now() - interval '1 minute')
+        "#
+    )
+    .execute(&app.db_conn_pool)
+    .await
+    .unwrap();
+
+    // ACT
+    let deleted = delete_expired_sessions(&app.db_conn_pool).await.unwrap();
+
+    // ASSERT
+    assert_eq!(deleted, 1);
+    let keys: Vec<String> = sqlx::query!("SELECT session_key FROM sessions")
+        .fetch_all(&app.db_conn_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.session_key)
+        .collect();
+    assert_eq!(keys.len(), 1);
+    assert_ne!(keys[0], "expired");
+    // The live session is untouched
+    assert!(app.get_admin_dashboard().await.status().is_success());
+}
+
+#[tokio::test]
+async fn expired_in_memory_sessions_are_purged() {
+    // ARRANGE
+    let store = InMemorySessionStore::default();
+    // Abandoned: never loaded again after it expired
+    store
+        .save(HashMap::new(), &Duration::seconds(-1))
+        .await
+        .unwrap();
+    let live = store
+        .save(HashMap::new(), &Duration::minutes(10))
+        .await
+        .unwrap();
+
+    // ACT
+    let purged = store.purge_expired().unwrap();
+
+    // ASSERT
+    assert_eq!(purged, 1);
+    // The live session is untouched
+    assert!(store.load(&live).await.unwrap().is_some());
+}
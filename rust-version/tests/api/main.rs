@@ -0,0 +1,21 @@
+//! tests/api/main.rs
+//! Single entrypoint for all our API tests: one test binary to compile and link,
+//! with the shared harness living in `helpers`.
+
+mod admin_dashboard;
+mod change_password;
+mod flash_messages;
+mod health_check;
+mod helpers;
+mod idempotency;
+mod issue_delivery;
+mod login;
+mod metrics;
+mod newsletters;
+mod problems;
+mod request_id;
+mod shutdown;
+mod startup;
+mod subscriptions;
+mod subscriptions_confirm;
+mod trace_export;
@@ -0,0 +1,155 @@
+//! tests/api/metrics.rs
+//! `/metrics`, in the Prometheus text format: HTTP traffic, the pool, and the newsletter pipeline.
+
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+
+impl TestApp {
+    /// From the admin listener, if there is one.
+    pub async fn get_metrics(&self) -> String {
+        let address = self.admin_address.as_ref().unwrap_or(&self.root_address);
+        let response = reqwest::get(format!("{}/metrics", address))
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 200);
+        response.text().await.unwrap()
+    }
+}
+
+/// The value of the `series` sample (name and labels, as exposed), if it is there.
+fn sample(metrics: &str, series: &str) -> Option<f64> {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route_template_and_status() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let unknown_path = format!("/subscriptions/{}", Uuid::new_v4());
+
+    // ACT
+    for _ in 0..2 {
+        reqwest::get(format!("{}/health_check", app.root_address))
+            .await
+            .unwrap();
+    }
+    app.get_confirm(&format!("?subscription_token={}", Uuid::new_v4()))
+        .await;
+    reqwest::get(format!("{}{}", app.root_address, unknown_path))
+        .await
+        .unwrap();
+    let metrics = app.get_metrics().await;
+
+    // ASSERT
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"http_requests_total{method="GET",route="/health_check",status="200"}"#
+        ),
+        Some(2.0)
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"http_request_duration_seconds_count{method="GET",route="/health_check",status="200"}"#
+        ),
+        Some(2.0)
+    );
+    // The template, not the path: one series, whatever the token
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"http_requests_total{method="GET",route="/subscriptions/confirm",status="400"}"#
+        ),
+        Some(1.0)
+    );
+    // Same for whatever matches no route at all
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"http_requests_total{method="GET",route="default",status="404"}"#
+        ),
+        Some(1.0)
+    );
+    assert!(!metrics.contains(&unknown_path), "{}", metrics);
+}
+
+#[tokio::test]
+async fn the_newsletter_pipeline_is_counted() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("le_guin@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>" }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let metrics = app.get_metrics().await;
+
+    // ASSERT
+    assert_eq!(sample(&metrics, "subscriptions_created_total"), Some(2.0));
+    assert_eq!(sample(&metrics, "subscriptions_confirmed_total"), Some(1.0));
+    assert_eq!(sample(&metrics, "emails_sent_total"), Some(1.0));
+    assert_eq!(sample(&metrics, "emails_failed_total"), Some(0.0));
+}
+
+#[tokio::test]
+async fn the_connection_pool_is_sampled_on_every_scrape() {
+    // ARRANGE
+    let app = spawn_app().await;
+    // At least one connection, opened by the health check
+    reqwest::get(format!("{}/health_check", app.root_address))
+        .await
+        .unwrap();
+
+    // ACT
+    let metrics = app.get_metrics().await;
+
+    // ASSERT
+    let connections = sample(&metrics, "db_pool_connections").expect("No pool size");
+    let idle = sample(&metrics, "db_pool_idle_connections").expect("No idle connections");
+    assert!(connections >= 1.0, "{}", metrics);
+    assert!(idle <= connections, "{}", metrics);
+}
+
+#[tokio::test]
+async fn metrics_move_to_the_admin_listener_when_there_is_one() {
+    // ARRANGE
+    let app = spawn_app_with(|config| config.server.admin_port = Some(0)).await;
+
+    // ACT
+    let public = reqwest::get(format!("{}/metrics", app.root_address))
+        .await
+        .unwrap();
+    let metrics = app.get_metrics().await;
+
+    // ASSERT
+    assert_eq!(public.status().as_u16(), 404);
+    assert!(metrics.contains("# TYPE http_requests_total counter"));
+    // That 404 is counted all the same
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"http_requests_total{method="GET",route="default",status="404"}"#
+        ),
+        Some(1.0)
+    );
+}
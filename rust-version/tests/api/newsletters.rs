@@ -0,0 +1,274 @@
+//! tests/api/newsletters.rs
+
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        // We assert that no request is fired at the email API!
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 202);
+    // mock verifies on drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 202);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["enqueued"], 1);
+}
+
+#[tokio::test]
+async fn publishing_only_enqueues_the_deliveries() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("octavia_butler@gmail.com")
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        // Nobody is draining the queue in this test
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let response = app.post_newsletters(newsletter_request_body()).await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 202);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["enqueued"], 2);
+    let queued = sqlx::query!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 2);
+}
+
+#[tokio::test]
+async fn subscribers_with_an_invalid_stored_email_are_skipped() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    // Bypassing the API: rows like this one predate email validation.
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status)
+        VALUES ($1, 'definitely-not-an-email', 'legacy', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_conn_pool)
+    .await
+    .expect("Failed to insert a legacy subscriber");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 202);
+    // Skipped tasks are dropped, not retried
+    let queued = sqlx::query!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_for_invalid_data() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        // ACT
+        let response = app.post_newsletters(invalid_body).await;
+
+        // ASSERT
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.root_address))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn non_existing_user_is_rejected() {
+    // ARRANGE
+    let app = spawn_app().await;
+    // Random credentials
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.root_address))
+        .basic_auth(username, Some(password))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_password_is_rejected() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let username = &app.test_user.username;
+    // Random password
+    let password = Uuid::new_v4().to_string();
+    assert_ne!(app.test_user.password, password);
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.root_address))
+        .basic_auth(username, Some(password))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn unknown_users_and_wrong_passwords_take_as_long_to_reject() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let attempt = |username: String| {
+        let address = app.root_address.clone();
+        async move {
+            let start = std::time::Instant::now();
+            let response = reqwest::Client::new()
+                .post(format!("{}/newsletters", address))
+                .basic_auth(username, Some(Uuid::new_v4().to_string()))
+                .json(&newsletter_request_body())
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            assert_eq!(401, response.status().as_u16());
+            start.elapsed()
+        }
+    };
+
+    // ACT
+    let wrong_password = attempt(app.test_user.username.clone()).await;
+    let unknown_user = attempt(Uuid::new_v4().to_string()).await;
+
+    // ASSERT
+    // Both paths pay for a full hash verification: an unknown user must NOT be
+    // rejected an order of magnitude faster (loose bound, to keep CI happy).
+    assert!(
+        unknown_user * 3 > wrong_password,
+        "unknown user: {:?}, wrong password: {:?}",
+        unknown_user,
+        wrong_password
+    );
+}
@@ -0,0 +1,130 @@
+//! tests/api/problems.rs
+//! Every error response is an `application/problem+json` (RFC 7807), whoever raised it.
+
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_problem, spawn_app};
+
+#[tokio::test]
+async fn validation_failures_list_every_offending_field() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app
+        .post_subscriptions("name=&email=definitely-not-an-email".into())
+        .await;
+
+    // ASSERT
+    let problem = assert_is_problem(response, 400).await;
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["instance"], "/subscription");
+    let fields: Vec<&str> = problem["invalid-params"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|param| param["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["name", "email"]);
+}
+
+#[tokio::test]
+async fn every_problem_carries_its_own_request_id() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let first = assert_is_problem(app.post_subscriptions("name=le%20guin".into()).await, 400).await;
+    let second =
+        assert_is_problem(app.post_subscriptions("name=le%20guin".into()).await, 400).await;
+
+    // ASSERT
+    let first = Uuid::parse_str(first["request_id"].as_str().unwrap()).unwrap();
+    let second = Uuid::parse_str(second["request_id"].as_str().unwrap()).unwrap();
+    assert_ne!(first, second);
+}
+
+#[tokio::test]
+async fn form_extractor_failures_name_the_missing_field() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.post_subscriptions("name=le%20guin".into()).await;
+
+    // ASSERT
+    let problem = assert_is_problem(response, 400).await;
+    assert_eq!(problem["invalid-params"][0]["name"], "email");
+}
+
+#[tokio::test]
+async fn query_extractor_failures_name_the_missing_field() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.get_confirm("").await;
+
+    // ASSERT
+    let problem = assert_is_problem(response, 400).await;
+    assert_eq!(problem["instance"], "/subscriptions/confirm");
+    assert_eq!(problem["invalid-params"][0]["name"], "subscription_token");
+}
+
+#[tokio::test]
+async fn json_extractor_failures_name_the_missing_field() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app
+        .post_newsletters(serde_json::json!({ "title": "Newsletter title" }))
+        .await;
+
+    // ASSERT
+    let problem = assert_is_problem(response, 400).await;
+    assert_eq!(problem["invalid-params"][0]["name"], "content");
+}
+
+#[tokio::test]
+async fn problems_keep_the_headers_of_the_original_response() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.root_address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>" }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="publish""#
+    );
+    let problem = assert_is_problem(response, 401).await;
+    assert_eq!(problem["detail"], "Authentication failed");
+}
+
+#[tokio::test]
+async fn errors_raised_outside_of_our_handlers_are_problems_too() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = reqwest::get(format!("{}/no/such/page", &app.root_address))
+        .await
+        .unwrap();
+
+    // ASSERT
+    let problem = assert_is_problem(response, 404).await;
+    assert_eq!(problem["title"], "Not Found");
+    assert_eq!(problem["instance"], "/no/such/page");
+    assert!(problem["request_id"].is_string());
+}
@@ -0,0 +1,139 @@
+//! tests/api/request_id.rs
+//! Every request gets an id: the caller's if it has a usable one, echoed back either way.
+
+use uuid::Uuid;
+
+use crate::helpers::{TestApp, assert_is_problem, spawn_app};
+
+impl TestApp {
+    pub async fn get_health_check_with(&self, headers: &[(&str, &str)]) -> reqwest::Response {
+        headers
+            .iter()
+            .fold(
+                reqwest::Client::new().get(format!("{}/health_check", &self.root_address)),
+                |request, (name, value)| request.header(*name, *value),
+            )
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+}
+
+fn request_id(response: &reqwest::Response) -> &str {
+    response
+        .headers()
+        .get("X-Request-Id")
+        .expect("No X-Request-Id header")
+        .to_str()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_request_id_is_generated_when_the_caller_has_none() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let first = app.get_health_check_with(&[]).await;
+    let second = app.get_health_check_with(&[]).await;
+
+    // ASSERT
+    let first = Uuid::parse_str(request_id(&first)).unwrap();
+    let second = Uuid::parse_str(request_id(&second)).unwrap();
+    assert_ne!(first, second);
+}
+
+#[tokio::test]
+async fn the_callers_request_id_is_echoed_back() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app
+        .get_health_check_with(&[("X-Request-Id", "lb-7f3a.42")])
+        .await;
+
+    // ASSERT
+    assert_eq!(request_id(&response), "lb-7f3a.42");
+}
+
+#[tokio::test]
+async fn the_trace_id_of_a_traceparent_is_used_as_request_id() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    // ACT
+    let response = app
+        .get_health_check_with(&[("traceparent", traceparent)])
+        .await;
+
+    // ASSERT
+    assert_eq!(request_id(&response), "4bf92f3577b34da6a3ce929d0e0e4736");
+}
+
+#[tokio::test]
+async fn an_x_request_id_header_wins_over_a_traceparent() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    // ACT
+    let response = app
+        .get_health_check_with(&[
+            ("X-Request-Id", "from-the-lb"),
+            ("traceparent", traceparent),
+        ])
+        .await;
+
+    // ASSERT
+    assert_eq!(request_id(&response), "from-the-lb");
+}
+
+#[tokio::test]
+async fn unusable_request_ids_are_replaced() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let too_long = "a".repeat(129);
+    let test_cases = vec![
+        ("X-Request-Id", "with spaces", "forbidden characters"),
+        ("X-Request-Id", too_long.as_str(), "too long"),
+        ("traceparent", "00-not-a-trace-01", "malformed traceparent"),
+        (
+            "traceparent",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "all-zero trace id",
+        ),
+    ];
+
+    for (header, value, description) in test_cases {
+        // ACT
+        let response = app.get_health_check_with(&[(header, value)]).await;
+
+        // ASSERT
+        assert!(
+            Uuid::parse_str(request_id(&response)).is_ok(),
+            "The request id was not replaced for {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn error_responses_carry_the_same_request_id_in_their_header_and_body() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = reqwest::Client::new()
+        .get(format!("{}/no/such/page", &app.root_address))
+        .header("X-Request-Id", "a-caller-id")
+        .send()
+        .await
+        .unwrap();
+
+    // ASSERT
+    assert_eq!(request_id(&response), "a-caller-id");
+    let problem = assert_is_problem(response, 404).await;
+    assert_eq!(problem["request_id"], "a-caller-id");
+}
@@ -0,0 +1,111 @@
+//! tests/api/shutdown.rs
+//! Stopping in an orderly fashion: in-flight work is finished, new work is turned away.
+
+use std::time::Duration;
+
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::get_configuration;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::metrics::Metrics;
+
+use crate::helpers::{spawn_app, spawn_app_with, spawn_binary};
+
+#[tokio::test]
+async fn readiness_fails_once_the_shutdown_has_started() {
+    // ARRANGE
+    let app = spawn_app_with(|config| config.shutdown.readiness_delay_milliseconds = 5000).await;
+
+    // ACT
+    app.shutdown.trigger();
+
+    // ASSERT
+    // Still serving, for the load balancer to find out
+    let response = app.get_health("ready").await;
+    assert_eq!(response.status().as_u16(), 503);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["status"], "shutting_down");
+    // Still alive, though: no reason to be restarted
+    assert_eq!(app.get_health("live").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn no_connections_are_accepted_once_the_readiness_delay_is_over() {
+    // ARRANGE
+    let app = spawn_app_with(|config| config.shutdown.readiness_delay_milliseconds = 200).await;
+
+    // ACT
+    app.shutdown.trigger();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // ASSERT
+    let outcome = reqwest::get(format!("{}/health/live", app.root_address)).await;
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn the_delivery_worker_stops_without_waiting_for_its_next_poll() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let mut settings = app.issue_delivery.clone();
+    settings.empty_queue_poll_milliseconds = 60_000;
+    let email_client = get_configuration()
+        .expect("Failed to read config")
+        .email_client
+        .client()
+        .expect("Invalid email client configuration.");
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.db_conn_pool.clone(),
+        email_client,
+        settings,
+        Metrics::new(),
+        app.shutdown.clone(),
+    ));
+    // Let it find the queue empty, and go to sleep
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // ACT
+    app.shutdown.trigger();
+
+    // ASSERT
+    let outcome = tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop");
+    assert!(outcome.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn sigterm_drains_in_flight_requests_then_exits_cleanly() {
+    // ARRANGE
+    let email_server = MockServer::start().await;
+    // The readiness check pings the email backend: slowly, keeping the request in flight
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(1000)))
+        .mount(&email_server)
+        .await;
+    let app = spawn_binary(&email_server, &[]).await;
+    let in_flight = tokio::spawn(reqwest::get(format!("{}/health/ready", app.address)));
+    while email_server.received_requests().await.unwrap().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // ACT
+    app.terminate();
+
+    // ASSERT
+    // The request that was already there is served...
+    let response = in_flight
+        .await
+        .unwrap()
+        .expect("The in-flight request was dropped");
+    assert_eq!(response.status().as_u16(), 200);
+    // ... new ones are turned away...
+    let refused = reqwest::get(format!("{}/health/live", app.address)).await;
+    assert!(refused.is_err());
+    // ... and the process exits on its own, having said so.
+    let (success, logs) = app.wait(Duration::from_secs(10)).await;
+    assert!(success, "{}", logs);
+    assert!(logs.contains("Received a shutdown signal"), "{}", logs);
+    assert!(logs.contains("Issue delivery worker stopped"), "{}", logs);
+    assert!(logs.contains("shutdown complete"), "{}", logs);
+}
@@ -0,0 +1,151 @@
+//! tests/api/startup.rs
+//! Booting without a database: the application serves anyway, and says what's wrong.
+
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use zero2prod::configuration::{Settings, get_configuration};
+use zero2prod::startup::{Application, get_connection_pool, wait_for_database};
+
+use crate::helpers::spawn_app;
+
+/// A port nobody is listening on (well, most likely).
+fn unused_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// The configuration, pointing at a database that isn't there.
+fn configuration_without_database() -> Settings {
+    let mut config = get_configuration().expect("Failed to read config");
+    config.database.port = unused_port();
+    config.database.pool.acquire_timeout_milliseconds = 500;
+    config.database.startup_probe.enabled = true;
+    config.database.startup_probe.max_attempts = 3;
+    config.database.startup_probe.base_backoff_milliseconds = 10;
+    config.database.startup_probe.max_backoff_milliseconds = 20;
+    config
+}
+
+/// Spawns the application against a database that isn't there, returning its address.
+// NOTE: no `spawn_app` here, since it creates and migrates a database.
+async fn spawn_app_without_database() -> String {
+    let mut config = configuration_without_database();
+    config.server.port = 0;
+    config.issue_delivery.workers = 0;
+    // The probe gives up, and the application starts anyway
+    let application = Application::build(config)
+        .await
+        .expect("Failed to build the application");
+    let port = application.port();
+    drop(tokio::spawn(application.run_until_stopped()));
+    format!("http://127.0.0.1:{}", port)
+}
+
+#[tokio::test]
+async fn the_application_serves_while_the_database_is_down() {
+    // ARRANGE
+    let address = spawn_app_without_database().await;
+    let client = reqwest::Client::new();
+
+    // ACT
+    let health = client
+        .get(format!("{}/health_check", address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let live = client
+        .get(format!("{}/health/live", address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let ready = client
+        .get(format!("{}/health/ready", address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let login_form = client
+        .get(format!("{}/login", address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    // Up, but not well
+    assert_eq!(health.status().as_u16(), 200);
+    let report: serde_json::Value = health.json().await.unwrap();
+    assert_eq!(report["database"], "unavailable");
+    // Alive, but not to be sent any traffic
+    assert_eq!(live.status().as_u16(), 200);
+    assert_eq!(ready.status().as_u16(), 503);
+    let report: serde_json::Value = ready.json().await.unwrap();
+    assert_eq!(report["checks"][0]["name"], "database");
+    assert_eq!(report["checks"][0]["status"], "down");
+    // Pages that don't need the database still work
+    assert_eq!(login_form.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn requests_needing_the_database_fail_fast_while_it_is_down() {
+    // ARRANGE
+    let address = spawn_app_without_database().await;
+    let started = Instant::now();
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscription", address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 500);
+    // Bounded by the pool's `acquire_timeout`, not left hanging
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn the_startup_probe_gives_up_after_max_attempts() {
+    // ARRANGE
+    let config = configuration_without_database();
+    let db_conn_pool = get_connection_pool(&config.database);
+
+    // ACT
+    let outcome = wait_for_database(&db_conn_pool, &config.database.startup_probe).await;
+
+    // ASSERT
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn a_disabled_startup_probe_does_not_wait() {
+    // ARRANGE
+    let mut config = configuration_without_database();
+    config.database.startup_probe.enabled = false;
+    let db_conn_pool = get_connection_pool(&config.database);
+
+    // ACT
+    let outcome = wait_for_database(&db_conn_pool, &config.database.startup_probe).await;
+
+    // ASSERT
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn the_startup_probe_returns_once_the_database_answers() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let mut config = get_configuration().expect("Failed to read config");
+    config.database.startup_probe.enabled = true;
+
+    // ACT
+    let outcome = wait_for_database(&app.db_conn_pool, &config.database.startup_probe).await;
+
+    // ASSERT
+    assert!(outcome.is_ok());
+}
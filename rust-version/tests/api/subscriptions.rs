@@ -0,0 +1,230 @@
+//! tests/api/subscriptions.rs
+
+use std::time::Duration;
+
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::routes::SubscribeError;
+
+use crate::helpers::{TestApp, assert_is_problem, spawn_app, spawn_binary};
+
+/// The email API accepts whatever it is sent.
+async fn accept_emails(email_server: &MockServer) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn subscribe_stores_a_subscription_token_for_the_new_subscriber() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    accept_emails(&app.email_server).await;
+
+    // ACT
+    app.post_subscriptions(body.into()).await;
+
+    // ASSERT
+    let saved = sqlx::query!(
+        r#"
+        SELECT subscription_tokens.subscription_token
+        FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+        WHERE subscriptions.email = 'ursula_le_guin@gmail.com'
+        "#
+    )
+    .fetch_one(&app.db_conn_pool)
+    .await
+    .expect("No subscription token was stored");
+    assert_eq!(saved.subscription_token.len(), 25);
+}
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    app.post_subscriptions(body.into()).await;
+
+    // ASSERT
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], "ursula_le_guin@gmail.com");
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // The same link in both bodies, pointing at the confirmation endpoint
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+    assert_eq!(confirmation_links.html.path(), "/subscriptions/confirm");
+    assert!(
+        confirmation_links
+            .html
+            .query()
+            .unwrap()
+            .starts_with("subscription_token=")
+    );
+}
+
+#[tokio::test]
+async fn subscribe_stores_nothing_if_the_confirmation_email_cannot_be_sent() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // ACT
+    let response = app.post_subscriptions(body.into()).await;
+
+    // ASSERT
+    assert_is_problem(response, 500).await;
+    // Nothing to be stuck with: trying again later is all it takes
+    assert_no_subscriber(&app).await;
+    app.email_server.reset().await;
+    accept_emails(&app.email_server).await;
+    let retry = app.post_subscriptions(body.into()).await;
+    assert_eq!(retry.status().as_u16(), 200);
+}
+
+async fn assert_no_subscriber(app: &TestApp) {
+    let stored = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.count, 0);
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_when_fields_are_present_but_invalid() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let test_cases = vec![
+        ("name=&email=ursula_le_guin%40gmail.com", "empty name"),
+        ("name=Ursula&email=", "empty email"),
+        ("name=Ursula&email=definitely-not-an-email", "invalid email"),
+        (
+            "name=%3Cscript%3E&email=ursula_le_guin%40gmail.com",
+            "name with forbidden characters",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // ACT
+        let response = client
+            .post(format!("{}/subscription", app.root_address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload was {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_fails_if_there_is_a_fatal_database_error() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    // Sabotage the database
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;")
+        .execute(&app.db_conn_pool)
+        .await
+        .unwrap();
+
+    // ACT
+    let response = app.post_subscriptions(body.into()).await;
+
+    // ASSERT
+    let problem = assert_is_problem(response, 500).await;
+    // None of our internals leak to the caller
+    assert!(problem.get("detail").is_none());
+    assert!(!problem.to_string().contains("subscription_token"));
+}
+
+#[test]
+fn subscribe_errors_carry_their_source_chain_in_their_debug_output() {
+    // ARRANGE
+    let error = SubscribeError::Storage(
+        "Failed to insert the new subscriber",
+        sqlx::Error::RowNotFound,
+    );
+
+    // ACT
+    let debug = format!("{:?}", error);
+
+    // ASSERT
+    assert!(debug.starts_with("Failed to insert the new subscriber\n"));
+    assert!(debug.contains(&format!("Caused by:\n\t{}", sqlx::Error::RowNotFound)));
+}
+
+/// Subscribes through the binary, with PII hashed in the logs: the response's status, and the logs.
+async fn subscribe_with_redaction_on(body: &'static str) -> (u16, String) {
+    let email_server = MockServer::start().await;
+    let key = "k".repeat(32);
+    let app = spawn_binary(
+        &email_server,
+        &[
+            ("APP_TELEMETRY__REDACTION__MODE", "hash"),
+            ("APP_TELEMETRY__REDACTION__KEY", &key),
+        ],
+    )
+    .await;
+    accept_emails(&email_server).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscription", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    app.terminate();
+    let (_, logs) = app.wait(Duration::from_secs(10)).await;
+    (response.status().as_u16(), logs)
+}
+
+#[tokio::test]
+async fn subscriber_emails_are_never_logged_raw_when_redaction_is_on() {
+    // ACT
+    let (status, logs) =
+        subscribe_with_redaction_on("name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+
+    // ASSERT
+    assert_eq!(status, 200);
+    assert!(logs.contains("ADDING A NEW SUBSCRIBER"), "{}", logs);
+    assert!(!logs.contains("ursula_le_guin@gmail.com"), "{}", logs);
+    assert!(logs.contains(r#""subscriber_email":"hmac:"#), "{}", logs);
+}
+
+#[tokio::test]
+async fn invalid_subscriber_details_are_never_logged_raw_when_redaction_is_on() {
+    // ACT
+    // Rejected: logged with the error, whose message must not echo them either
+    let (status, logs) =
+        subscribe_with_redaction_on("name=le%20guin%7B%7D&email=ursula_le_guin.gmail.com").await;
+
+    // ASSERT
+    assert_eq!(status, 400);
+    assert!(logs.contains("Request rejected"), "{}", logs);
+    assert!(!logs.contains("ursula_le_guin"), "{}", logs);
+    assert!(!logs.contains("le guin"), "{}", logs);
+}
@@ -0,0 +1,112 @@
+//! tests/api/subscriptions_confirm.rs
+
+use crate::helpers::spawn_app;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+#[tokio::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app.get_confirm("").await;
+
+    // ASSERT
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn confirmations_with_a_malformed_token_are_rejected_with_a_400() {
+    // ARRANGE
+    let app = spawn_app().await;
+    // (query, the token as the handler sees it, description)
+    let test_cases = vec![
+        ("?subscription_token=", "", "an empty token"),
+        (
+            "?subscription_token=tooshort",
+            "tooshort",
+            "a token that is too short",
+        ),
+        (
+            "?subscription_token=abcdefghijklmnopqrstuvw%3B-",
+            "abcdefghijklmnopqrstuvw;-",
+            "a token with non-alphanumeric characters",
+        ),
+    ];
+
+    for (query, token, description) in test_cases {
+        // ACT
+        let response = app.get_confirm(query).await;
+
+        // ASSERT
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request for {}.",
+            description
+        );
+        // Whatever was sent is not echoed back
+        let body = response.text().await.unwrap();
+        assert!(
+            token.is_empty() || !body.contains(token),
+            "The token was echoed back for {}: {}",
+            description,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    // well-formed, but was never issued
+    let response = app
+        .get_confirm("?subscription_token=aaaaaaaaaaaaaaaaaaaaaaaaa")
+        .await;
+
+    // ASSERT
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber(EMAIL).await;
+
+    // ACT
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // ASSERT
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions;")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, EMAIL);
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirming_twice_is_idempotent() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber(EMAIL).await;
+
+    // ACT
+    let first = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    let second = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // ASSERT
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions;")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
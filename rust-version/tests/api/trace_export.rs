@@ -0,0 +1,430 @@
+//! tests/api/trace_export.rs
+//! Spans exported over OTLP, to an in-process collector: as one trace with our callers',
+//! and with the email API, and with their PII redacted.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{
+    TraceService, TraceServiceServer,
+};
+use opentelemetry_proto::tonic::collector::trace::v1::{
+    ExportTraceServiceRequest, ExportTraceServiceResponse,
+};
+use opentelemetry_proto::tonic::common::v1::{KeyValue, any_value};
+use opentelemetry_proto::tonic::trace::v1::{ResourceSpans, Span};
+use prost::Message;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::get_configuration;
+
+use crate::helpers::{SpawnedBinary, TestUser, spawn_binary};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+/// Stands in for an OpenTelemetry collector, over gRPC: keeps whatever it is sent.
+#[derive(Clone, Default)]
+struct OtlpCollector {
+    exported: Arc<Mutex<Vec<ResourceSpans>>>,
+}
+
+#[tonic::async_trait]
+impl TraceService for OtlpCollector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        self.exported
+            .lock()
+            .unwrap()
+            .extend(request.into_inner().resource_spans);
+        Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+impl OtlpCollector {
+    /// Serves on a random port, in the background: returns its base URL.
+    fn start(&self) -> String {
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = format!("http://{}", incoming.local_addr().unwrap());
+        tokio::spawn(
+            Server::builder()
+                .add_service(TraceServiceServer::new(self.clone()))
+                .serve_with_incoming(incoming),
+        );
+        address
+    }
+}
+
+async fn spawn_traced_binary(
+    email_server: &MockServer,
+    protocol: &str,
+    endpoint: &str,
+    env: &[(&str, &str)],
+) -> SpawnedBinary {
+    let telemetry = [
+        ("APP_TELEMETRY__SERVICE_NAME", "zero2prod-under-test"),
+        ("APP_TELEMETRY__SAMPLING_RATIO", "1.0"),
+        (
+            "APP_TELEMETRY__RESOURCE_ATTRIBUTES",
+            "deployment.environment=test",
+        ),
+        ("APP_TELEMETRY__OTLP__PROTOCOL", protocol),
+        ("APP_TELEMETRY__OTLP__ENDPOINT", endpoint),
+        ("APP_TELEMETRY__OTLP__TIMEOUT_MILLISECONDS", "5000"),
+    ];
+    spawn_binary(email_server, &[&telemetry[..], env].concat()).await
+}
+
+/// Stops the binary: spans still in their batch are flushed on the way out.
+async fn stop(app: SpawnedBinary) {
+    app.terminate();
+    let (success, logs) = app.wait(Duration::from_secs(10)).await;
+    assert!(success, "{}", logs);
+}
+
+fn string_attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.key == key)
+        .and_then(|attribute| attribute.value.as_ref()?.value.as_ref())
+        .and_then(|value| match value {
+            any_value::Value::StringValue(value) => Some(value.as_str()),
+            _ => None,
+        })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn spans(exported: &[ResourceSpans]) -> Vec<&Span> {
+    exported
+        .iter()
+        .flat_map(|resource_spans| &resource_spans.scope_spans)
+        .flat_map(|scope_spans| &scope_spans.spans)
+        .collect()
+}
+
+/// The root span of `GET /health_check`, as a child of `TRACEPARENT`, from the right service.
+fn assert_health_check_was_traced(exported: &[ResourceSpans]) {
+    let resource = exported
+        .first()
+        .and_then(|resource_spans| resource_spans.resource.as_ref())
+        .expect("No span was exported");
+    assert_eq!(
+        string_attribute(&resource.attributes, "service.name"),
+        Some("zero2prod-under-test")
+    );
+    assert_eq!(
+        string_attribute(&resource.attributes, "deployment.environment"),
+        Some("test")
+    );
+
+    let spans = spans(exported);
+    let request_span = spans
+        .iter()
+        .find(|span| span.name == "GET /health_check")
+        .unwrap_or_else(|| panic!("No request span in {:?}", spans));
+    assert_eq!(hex(&request_span.trace_id), TRACE_ID);
+    assert_eq!(hex(&request_span.parent_span_id), PARENT_ID);
+    assert_eq!(
+        string_attribute(&request_span.attributes, "http.route"),
+        Some("/health_check")
+    );
+}
+
+#[tokio::test]
+async fn spans_are_exported_over_grpc_as_part_of_the_callers_trace() {
+    // ARRANGE
+    let email_server = MockServer::start().await;
+    let collector = OtlpCollector::default();
+    let endpoint = collector.start();
+    let app = spawn_traced_binary(&email_server, "grpc", &endpoint, &[]).await;
+
+    // ACT
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", app.address))
+        .header("traceparent", TRACEPARENT)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    stop(app).await;
+
+    // ASSERT
+    assert_health_check_was_traced(&collector.exported.lock().unwrap());
+}
+
+#[tokio::test]
+async fn spans_are_exported_over_http_as_part_of_the_callers_trace() {
+    // ARRANGE
+    let email_server = MockServer::start().await;
+    let collector = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/traces"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&collector)
+        .await;
+    let app = spawn_traced_binary(&email_server, "http", &collector.uri(), &[]).await;
+
+    // ACT
+    reqwest::Client::new()
+        .get(format!("{}/health_check", app.address))
+        .header("traceparent", TRACEPARENT)
+        .send()
+        .await
+        .unwrap();
+    stop(app).await;
+
+    // ASSERT
+    let exported: Vec<ResourceSpans> = collector
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .flat_map(|request| {
+            ExportTraceServiceRequest::decode(request.body.as_slice())
+                .expect("Not an OTLP protobuf payload")
+                .resource_spans
+        })
+        .collect();
+    assert_health_check_was_traced(&exported);
+}
+
+#[tokio::test]
+async fn the_trace_of_a_publication_is_propagated_to_the_email_api() {
+    // ARRANGE
+    let email_server = MockServer::start().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&email_server)
+        .await;
+    let collector = OtlpCollector::default();
+    let endpoint = collector.start();
+    let app = spawn_traced_binary(
+        &email_server,
+        "grpc",
+        &endpoint,
+        // No waiting around for the delivery worker
+        &[("APP_ISSUE_DELIVERY__EMPTY_QUEUE_POLL_MILLISECONDS", "50")],
+    )
+    .await;
+    let publisher = TestUser::generate();
+    let config = get_configuration().expect("Failed to read config");
+    publisher
+        .store(&app.db_conn_pool, &config.password.hashing)
+        .await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_conn_pool)
+    .await
+    .unwrap();
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", app.address))
+        .basic_auth(&publisher.username, Some(&publisher.password))
+        .header("traceparent", TRACEPARENT)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Newsletter body as plain text", "html": "<p>Newsletter body as HTML</p>" }
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    // Delivered in the background, by the worker
+    let started = Instant::now();
+    while email_server.received_requests().await.unwrap().is_empty() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "The issue was not delivered"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    stop(app).await;
+
+    // ASSERT
+    let email_request = &email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request
+        .headers
+        .get("traceparent")
+        .expect("No traceparent sent to the email API")
+        .to_str()
+        .unwrap();
+    let mut parts = traceparent.split('-');
+    assert_eq!(parts.nth(1), Some(TRACE_ID));
+    // A span of ours, not the caller's
+    assert_ne!(parts.next(), Some(PARENT_ID));
+    // ... the one the delivery span exported, as part of the same trace
+    let exported = collector.exported.lock().unwrap();
+    let spans = spans(&exported);
+    let delivery_span = spans
+        .iter()
+        .find(|span| span.name == "Delivering a newsletter issue")
+        .expect("No delivery span");
+    assert_eq!(hex(&delivery_span.trace_id), TRACE_ID);
+}
+
+#[tokio::test]
+async fn pii_is_redacted_from_the_exported_spans() {
+    // ARRANGE
+    let email_server = MockServer::start().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&email_server)
+        .await;
+    let collector = OtlpCollector::default();
+    let endpoint = collector.start();
+    let key = "k".repeat(32);
+    let app = spawn_traced_binary(
+        &email_server,
+        "grpc",
+        &endpoint,
+        &[
+            ("APP_TELEMETRY__REDACTION__MODE", "hash"),
+            ("APP_TELEMETRY__REDACTION__KEY", &key),
+        ],
+    )
+    .await;
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscription", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    stop(app).await;
+
+    // ASSERT
+    let exported = collector.exported.lock().unwrap();
+    let spans = spans(&exported);
+    let subscribe_span = spans
+        .iter()
+        .find(|span| span.name == "Adding a new subscriber")
+        .unwrap_or_else(|| panic!("No subscribe span in {:?}", spans));
+    let email = string_attribute(&subscribe_span.attributes, "subscriber_email").unwrap();
+    assert!(email.starts_with("hmac:"), "{}", email);
+    // Nowhere to be found: neither on the spans nor on their events
+    let exported = format!("{:?}", spans);
+    assert!(
+        !exported.contains("ursula_le_guin@gmail.com"),
+        "{}",
+        exported
+    );
+    assert!(!exported.contains("le guin"), "{}", exported);
+}
+
+/// Just enough of an SMTP relay to turn every recipient down, quoting their address as
+/// Postfix does: "550 5.1.1 <ursula_le_guin@gmail.com>: Recipient address rejected".
+/// Returns its port.
+async fn start_rejecting_smtp_relay() -> u16 {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let _ = writer.write_all(b"220 fake.smtp ESMTP ready\r\n").await;
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let command = line.to_ascii_uppercase();
+                    let reply = if command.starts_with("RCPT TO:") {
+                        format!("550 5.1.1 {}: Recipient address rejected\r\n", &line[8..])
+                    } else if command == "QUIT" {
+                        "221 Bye\r\n".to_string()
+                    } else {
+                        "250 OK\r\n".to_string()
+                    };
+                    if writer.write_all(reply.as_bytes()).await.is_err() || command == "QUIT" {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn addresses_quoted_by_a_rejecting_smtp_relay_are_redacted() {
+    // ARRANGE
+    // Not called: emails go through the SMTP relay
+    let email_server = MockServer::start().await;
+    let relay_port = start_rejecting_smtp_relay().await.to_string();
+    let collector = OtlpCollector::default();
+    let endpoint = collector.start();
+    let key = "k".repeat(32);
+    let app = spawn_traced_binary(
+        &email_server,
+        "grpc",
+        &endpoint,
+        &[
+            ("APP_TELEMETRY__REDACTION__MODE", "hash"),
+            ("APP_TELEMETRY__REDACTION__KEY", &key),
+            ("APP_EMAIL_CLIENT__KIND", "smtp"),
+            ("APP_EMAIL_CLIENT__HOST", "127.0.0.1"),
+            ("APP_EMAIL_CLIENT__PORT", &relay_port),
+            ("APP_EMAIL_CLIENT__TLS", "none"),
+        ],
+    )
+    .await;
+
+    // ACT
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscription", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 500);
+    app.terminate();
+    let (success, logs) = app.wait(Duration::from_secs(10)).await;
+    assert!(success, "{}", logs);
+
+    // ASSERT
+    // The relay's reply is logged with the error, its address hashed
+    let failure = logs
+        .lines()
+        .find(|line| line.contains("Recipient address rejected"))
+        .unwrap_or_else(|| panic!("The rejection was not logged: {}", logs));
+    assert!(failure.contains("<hmac:"), "{}", failure);
+    assert!(!logs.contains("ursula_le_guin@gmail.com"), "{}", logs);
+    // Same for the error, as an event of the exported request span
+    let exported = collector.exported.lock().unwrap();
+    let spans = spans(&exported);
+    let cause_chain = spans
+        .iter()
+        .flat_map(|span| &span.events)
+        .find_map(|event| string_attribute(&event.attributes, "error.cause_chain"))
+        .unwrap_or_else(|| panic!("No error was exported: {:?}", spans));
+    assert!(
+        cause_chain.contains("Recipient address rejected") && cause_chain.contains("<hmac:"),
+        "{}",
+        cause_chain
+    );
+    let exported = format!("{:?}", spans);
+    assert!(
+        !exported.contains("ursula_le_guin@gmail.com"),
+        "{}",
+        exported
+    );
+}
@@ -0,0 +1,273 @@
+//! tests/configuration.rs
+//! Layered configuration: files per environment, environment variables on top.
+
+use secrecy::{ExposeSecret, SecretString};
+use std::time::Duration;
+
+use sqlx::postgres::PgSslMode;
+use zero2prod::configuration::{
+    DBUser, DatabasePoolSettings, DatabaseSettings, DatabaseSslMode, DatabaseStartupProbeSettings,
+    EmailTransportSettings, Environment, RedactionMode, RedactionSettings, TelemetrySettings,
+    get_configuration,
+};
+
+fn database_settings() -> DatabaseSettings {
+    DatabaseSettings {
+        name: "newsletter".into(),
+        host: "127.0.0.1".into(),
+        port: 5432,
+        user: DBUser {
+            name: "postgres".into(),
+            password: SecretString::from("super-secret-db-password"),
+        },
+        require_ssl: false,
+        ssl_mode: None,
+        ssl_root_cert: None,
+        pool: DatabasePoolSettings {
+            max_connections: 7,
+            min_connections: 2,
+            acquire_timeout_milliseconds: 1500,
+            idle_timeout_seconds: Some(60),
+            max_lifetime_seconds: None,
+            statement_cache_capacity: 10,
+        },
+        startup_probe: DatabaseStartupProbeSettings {
+            enabled: true,
+            max_attempts: 5,
+            base_backoff_milliseconds: 100,
+            max_backoff_milliseconds: 300,
+        },
+    }
+}
+
+#[test]
+fn known_environments_are_parsed_case_insensitively() {
+    assert_eq!(
+        Environment::try_from("local".to_string()),
+        Ok(Environment::Local)
+    );
+    assert_eq!(
+        Environment::try_from("PRODUCTION".to_string()),
+        Ok(Environment::Production)
+    );
+}
+
+#[test]
+fn unknown_environments_are_rejected() {
+    for environment in ["", "staging", "prod"] {
+        assert!(
+            Environment::try_from(environment.to_string()).is_err(),
+            "`{}` was accepted as an environment",
+            environment
+        );
+    }
+}
+
+#[test]
+fn secrets_are_redacted_from_debug_output() {
+    let database = database_settings();
+    let email_transport = EmailTransportSettings::Http {
+        base_url: "http://localhost".into(),
+        authorization_token: SecretString::from("super-secret-api-token"),
+    };
+
+    let database_debug = format!("{:?}", database);
+    let email_transport_debug = format!("{:?}", email_transport);
+
+    assert!(!database_debug.contains("super-secret-db-password"));
+    assert!(database_debug.contains("REDACTED"));
+    assert!(!email_transport_debug.contains("super-secret-api-token"));
+    // Still there, for whoever explicitly asks
+    assert_eq!(
+        database.user.password.expose_secret(),
+        "super-secret-db-password"
+    );
+}
+
+#[test]
+fn the_ssl_mode_defaults_to_prefer_and_can_be_required() {
+    let mut database = database_settings();
+    assert_eq!(database.ssl_mode(), DatabaseSslMode::Prefer);
+    assert!(matches!(
+        database.with_db().get_ssl_mode(),
+        PgSslMode::Prefer
+    ));
+
+    database.require_ssl = true;
+    assert!(matches!(
+        database.with_db().get_ssl_mode(),
+        PgSslMode::Require
+    ));
+
+    // An explicit mode wins over the shorthand
+    database.ssl_mode = Some(DatabaseSslMode::VerifyFull);
+    assert!(matches!(
+        database.with_db().get_ssl_mode(),
+        PgSslMode::VerifyFull
+    ));
+}
+
+#[test]
+fn ssl_modes_are_read_in_kebab_case() {
+    for (raw, expected) in [
+        ("disable", DatabaseSslMode::Disable),
+        ("prefer", DatabaseSslMode::Prefer),
+        ("require", DatabaseSslMode::Require),
+        ("verify-ca", DatabaseSslMode::VerifyCa),
+        ("verify-full", DatabaseSslMode::VerifyFull),
+    ] {
+        let parsed: DatabaseSslMode = serde_json::from_value(raw.into()).unwrap();
+        assert_eq!(parsed, expected);
+    }
+    assert!(serde_json::from_value::<DatabaseSslMode>("allow".into()).is_err());
+}
+
+#[test]
+fn pool_settings_feed_the_pool_options() {
+    let database = database_settings();
+
+    let options = database.pool_options();
+
+    assert_eq!(options.get_max_connections(), 7);
+    assert_eq!(options.get_min_connections(), 2);
+    assert_eq!(options.get_acquire_timeout(), Duration::from_millis(1500));
+    assert_eq!(options.get_idle_timeout(), Some(Duration::from_secs(60)));
+    assert_eq!(options.get_max_lifetime(), None);
+}
+
+#[test]
+fn the_startup_probe_backs_off_exponentially_up_to_a_ceiling() {
+    let probe = database_settings().startup_probe;
+
+    let backoffs: Vec<_> = (1..=4).map(|attempt| probe.backoff(attempt)).collect();
+
+    assert_eq!(
+        backoffs,
+        vec![
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_millis(300),
+            Duration::from_millis(300),
+        ]
+    );
+}
+
+#[test]
+fn resource_attributes_are_read_as_comma_separated_pairs() {
+    let telemetry = |resource_attributes: &str| TelemetrySettings {
+        service_name: "zero2prod".into(),
+        otlp: None,
+        sampling_ratio: 1.0,
+        resource_attributes: resource_attributes.into(),
+        redaction: RedactionSettings {
+            mode: RedactionMode::Off,
+            key: None,
+        },
+    };
+
+    assert_eq!(
+        telemetry(" deployment.environment=production, team = newsletter ,")
+            .resource_attributes()
+            .unwrap(),
+        vec![
+            (
+                "deployment.environment".to_string(),
+                "production".to_string()
+            ),
+            ("team".to_string(), "newsletter".to_string()),
+        ]
+    );
+    assert_eq!(telemetry("").resource_attributes().unwrap(), vec![]);
+    for malformed in ["deployment.environment", "=production", "a=b,c"] {
+        assert!(
+            telemetry(malformed).resource_attributes().is_err(),
+            "`{}` was accepted",
+            malformed
+        );
+    }
+}
+
+// ONE test for everything that touches the process environment: tests in a binary run
+// concurrently, and environment variables are global to the process.
+#[test]
+fn the_environment_selects_the_configuration_and_app_variables_override_it() {
+    // Local (the default)
+    let settings = get_configuration().expect("Failed to read the local configuration");
+    assert_eq!(settings.server.host, "127.0.0.1");
+    assert!(!settings.session.cookie_secure);
+    assert_eq!(settings.server.admin_port, None);
+    // Nowhere to export spans to, unless asked
+    assert!(settings.telemetry.otlp.is_none());
+    assert_eq!(settings.telemetry.redaction.mode, RedactionMode::Off);
+
+    // SAFETY: no other test in this binary reads or writes the environment.
+    unsafe {
+        std::env::set_var("APP_ENVIRONMENT", "production");
+        // Production expects its secrets from the environment
+        std::env::set_var("APP_APPLICATION__HMAC_SECRET", "x".repeat(64));
+        std::env::set_var("APP_APPLICATION__BASE_URL", "https://zero2prod.com");
+        // Ports are strings in the environment
+        std::env::set_var("APP_DATABASE__PORT", "6543");
+        std::env::set_var("APP_SERVER__PORT", "8080");
+        std::env::set_var("APP_SERVER__ADMIN_PORT", "9000");
+        // Even in the flattened, tagged email transport
+        std::env::set_var("APP_EMAIL_CLIENT__KIND", "smtp");
+        std::env::set_var("APP_EMAIL_CLIENT__HOST", "smtp.internal");
+        std::env::set_var("APP_EMAIL_CLIENT__PORT", "2525");
+        std::env::set_var("APP_EMAIL_CLIENT__TLS", "none");
+        std::env::set_var("APP_EMAIL_CLIENT__POOL_MAX_SIZE", "3");
+        std::env::set_var("APP_EMAIL_CLIENT__TIMEOUT_MILLISECONDS", "500");
+    }
+    let settings = get_configuration().expect("Failed to read the production configuration");
+    assert_eq!(settings.server.host, "0.0.0.0");
+    assert!(settings.session.cookie_secure);
+    assert_eq!(settings.database.ssl_mode(), DatabaseSslMode::Require);
+    assert_eq!(
+        settings.application.hmac_secret.expose_secret(),
+        "x".repeat(64)
+    );
+    assert_eq!(settings.application.base_url, "https://zero2prod.com");
+    assert_eq!(settings.database.port, 6543);
+    assert_eq!(settings.server.port, 8080);
+    assert_eq!(settings.server.admin_port, Some(9000));
+    assert_eq!(settings.email_client.timeout_milliseconds, 500);
+    match settings.email_client.transport {
+        EmailTransportSettings::Smtp(smtp) => {
+            assert_eq!(smtp.host, "smtp.internal");
+            assert_eq!(smtp.port, 2525);
+            assert_eq!(smtp.pool_max_size, 3);
+        }
+        other => panic!("Expected the SMTP transport, got {:?}", other),
+    }
+    // Untouched keys keep their `base.yaml` value
+    assert_eq!(settings.database.name, "newsletter");
+    assert_eq!(settings.telemetry.service_name, "zero2prod");
+    assert_eq!(settings.telemetry.sampling_ratio, 0.1);
+    assert_eq!(settings.telemetry.redaction.mode, RedactionMode::Hash);
+
+    // SAFETY: see above
+    unsafe {
+        std::env::set_var("APP_ENVIRONMENT", "staging");
+    }
+    assert!(get_configuration().is_err());
+
+    // SAFETY: see above
+    unsafe {
+        for key in [
+            "APP_ENVIRONMENT",
+            "APP_APPLICATION__HMAC_SECRET",
+            "APP_APPLICATION__BASE_URL",
+            "APP_DATABASE__PORT",
+            "APP_SERVER__PORT",
+            "APP_SERVER__ADMIN_PORT",
+            "APP_EMAIL_CLIENT__KIND",
+            "APP_EMAIL_CLIENT__HOST",
+            "APP_EMAIL_CLIENT__PORT",
+            "APP_EMAIL_CLIENT__TLS",
+            "APP_EMAIL_CLIENT__POOL_MAX_SIZE",
+            "APP_EMAIL_CLIENT__TIMEOUT_MILLISECONDS",
+        ] {
+            std::env::remove_var(key);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! tests/domain.rs
+
+use fake::Fake;
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use quickcheck::{Arbitrary, Gen};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use zero2prod::domain::{SubscriberEmail, SubscriberName, SubscriptionToken};
+
+// SUBSCRIBER NAME
+
+#[test]
+fn a_256_grapheme_long_name_is_valid() {
+    let name = "ё".repeat(256);
+    assert!(SubscriberName::parse(name).is_ok());
+}
+
+#[test]
+fn a_name_longer_than_256_graphemes_is_rejected() {
+    let name = "a".repeat(257);
+    assert!(SubscriberName::parse(name).is_err());
+}
+
+#[test]
+fn whitespace_only_names_are_rejected() {
+    let name = " ".to_string();
+    assert!(SubscriberName::parse(name).is_err());
+}
+
+#[test]
+fn empty_string_is_rejected() {
+    let name = "".to_string();
+    assert!(SubscriberName::parse(name).is_err());
+}
+
+#[test]
+fn names_containing_an_invalid_character_are_rejected() {
+    for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
+        let name = name.to_string();
+        assert!(
+            SubscriberName::parse(name.clone()).is_err(),
+            "{} should have been rejected",
+            name
+        );
+    }
+}
+
+// A fixture type, so that quickcheck knows how to generate realistic names.
+// We can't implement `Arbitrary` (a foreign trait) for `String` (a foreign type): orphan rule!
+// SCALA EQUIVALENT: an `Arbitrary[ValidName]` instance for ScalaCheck
+#[derive(Debug, Clone)]
+struct ValidNameFixture(pub String);
+
+impl Arbitrary for ValidNameFixture {
+    fn arbitrary(g: &mut Gen) -> Self {
+        // quickcheck's `Gen` and fake's `Rng` are unrelated types:
+        // we bridge them by seeding a `rand` RNG from quickcheck's randomness.
+        let mut rng = StdRng::seed_from_u64(u64::arbitrary(g));
+        Self(Name().fake_with_rng(&mut rng))
+    }
+}
+
+#[quickcheck_macros::quickcheck]
+fn realistic_names_are_parsed_successfully(valid_name: ValidNameFixture) -> bool {
+    SubscriberName::parse(valid_name.0).is_ok()
+}
+
+// SUBSCRIBER EMAIL
+
+#[test]
+fn empty_email_is_rejected() {
+    let email = "".to_string();
+    assert!(SubscriberEmail::parse(email).is_err());
+}
+
+#[test]
+fn email_missing_at_symbol_is_rejected() {
+    let email = "ursuladomain.com".to_string();
+    assert!(SubscriberEmail::parse(email).is_err());
+}
+
+#[test]
+fn email_missing_subject_is_rejected() {
+    let email = "@domain.com".to_string();
+    assert!(SubscriberEmail::parse(email).is_err());
+}
+
+#[derive(Debug, Clone)]
+struct ValidEmailFixture(pub String);
+
+impl Arbitrary for ValidEmailFixture {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let mut rng = StdRng::seed_from_u64(u64::arbitrary(g));
+        Self(SafeEmail().fake_with_rng(&mut rng))
+    }
+}
+
+#[quickcheck_macros::quickcheck]
+fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
+    SubscriberEmail::parse(valid_email.0).is_ok()
+}
+
+// SUBSCRIPTION TOKEN
+
+#[test]
+fn generated_tokens_are_valid() {
+    let token = SubscriptionToken::generate();
+    assert!(SubscriptionToken::parse(token.as_ref().to_string()).is_ok());
+}
+
+#[test]
+fn tokens_of_the_wrong_length_are_rejected() {
+    for token in ["", "a", &"a".repeat(24), &"a".repeat(26)] {
+        assert!(
+            SubscriptionToken::parse(token.to_string()).is_err(),
+            "{} should have been rejected",
+            token
+        );
+    }
+}
+
+#[test]
+fn tokens_with_non_alphanumeric_characters_are_rejected() {
+    let token = format!("{}-", "a".repeat(24));
+    assert!(SubscriptionToken::parse(token).is_err());
+}
@@ -0,0 +1,184 @@
+//! tests/email_client.rs
+
+use std::time::Duration;
+
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::lorem::en::{Paragraph, Sentence};
+use fake::{Fake, Faker};
+use secrecy::SecretString;
+use wiremock::matchers::{any, header, header_exists, method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_client::{EmailClient, HttpTransport};
+
+// A custom wiremock matcher: the request body must be JSON with all the fields the API expects.
+// SCALA: like writing your own `Matcher[Request]`
+struct SendEmailBodyMatcher;
+
+impl wiremock::Match for SendEmailBodyMatcher {
+    fn matches(&self, request: &Request) -> bool {
+        let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+        if let Ok(body) = result {
+            body.get("From").is_some()
+                && body.get("To").is_some()
+                && body.get("Subject").is_some()
+                && body.get("HtmlBody").is_some()
+                && body.get("TextBody").is_some()
+        } else {
+            false
+        }
+    }
+}
+
+fn subject() -> String {
+    Sentence(1..2).fake()
+}
+
+fn content() -> String {
+    Paragraph(1..10).fake()
+}
+
+fn email() -> SubscriberEmail {
+    SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+}
+
+fn email_client(base_url: String) -> EmailClient {
+    EmailClient::new(
+        email(),
+        HttpTransport::new(
+            base_url,
+            SecretString::from(Faker.fake::<String>()),
+            Duration::from_millis(200),
+        )
+        .unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn send_email_sends_the_expected_request() {
+    // ARRANGE
+    // A real HTTP server, on a random port, that we can program with expectations.
+    let mock_server = MockServer::start().await;
+    let email_client = email_client(mock_server.uri());
+
+    Mock::given(header_exists("X-Postmark-Server-Token"))
+        .and(header("Content-Type", "application/json"))
+        .and(path("/email"))
+        .and(method("POST"))
+        .and(SendEmailBodyMatcher)
+        .respond_with(ResponseTemplate::new(200))
+        // The expectation is verified when `mock_server` goes out of scope
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // ACT
+    let _ = email_client
+        .send_email(&email(), &subject(), &content(), &content())
+        .await;
+
+    // ASSERT: on drop of `mock_server`
+}
+
+#[tokio::test]
+async fn send_email_succeeds_if_the_server_returns_200() {
+    // ARRANGE
+    let mock_server = MockServer::start().await;
+    let email_client = email_client(mock_server.uri());
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // ACT
+    let outcome = email_client
+        .send_email(&email(), &subject(), &content(), &content())
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn send_email_fails_if_the_server_returns_500() {
+    // ARRANGE
+    let mock_server = MockServer::start().await;
+    let email_client = email_client(mock_server.uri());
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // ACT
+    let outcome = email_client
+        .send_email(&email(), &subject(), &content(), &content())
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn send_email_times_out_if_the_server_takes_too_long() {
+    // ARRANGE
+    let mock_server = MockServer::start().await;
+    let email_client = email_client(mock_server.uri());
+
+    // Well beyond the client's 200ms timeout
+    let response = ResponseTemplate::new(200).set_delay(Duration::from_secs(180));
+    Mock::given(any())
+        .respond_with(response)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // ACT
+    let outcome = email_client
+        .send_email(&email(), &subject(), &content(), &content())
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn ping_succeeds_whatever_the_server_answers() {
+    // ARRANGE
+    let mock_server = MockServer::start().await;
+    let email_client = email_client(mock_server.uri());
+    // No ping endpoint in the API: a 404 is as good as anything
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // ACT
+    let outcome = email_client.ping().await;
+
+    // ASSERT
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn ping_fails_if_the_server_cannot_be_reached() {
+    // ARRANGE
+    // Nobody listening on the other end
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let email_client = email_client(format!("http://127.0.0.1:{}", port));
+
+    // ACT
+    let outcome = email_client.ping().await;
+
+    // ASSERT
+    assert!(outcome.is_err());
+}
@@ -1,15 +1,22 @@
 //! tests/health_check.rs
 
-use std::net::TcpListener; // For compile-time string formatting
-
+use secrecy::SecretString;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 
 use uuid::Uuid;
-use zero2prod::configuration::{DBUser, DatabaseSettings, Settings, get_configuration};
-
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{
+    DBUser, DatabaseSettings, EmailTransportSettings, Settings, get_configuration,
+};
+use zero2prod::startup::{Application, get_connection_pool};
+
+// NOTE: the rest of the API is tested in `tests/api`, with a shared harness (`helpers`);
+// this one stays as small as it started out.
 pub struct TestApp {
     root_address: String,
     db_conn_pool: PgPool,
+    email_server: MockServer,
 }
 
 // `tokio::test` is the testing equivalent of `tokio::main`.
@@ -34,7 +41,8 @@ async fn health_check_works() {
 
     // ASSERT
     assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["database"], "available");
 
     // A NOTE ON CLEAN-UP / TEARDOWN
     // when a tokio runtime is shut down all tasks spawned on it are dropped.
@@ -46,17 +54,24 @@ async fn subscribe_returns_200_ok_for_valid_form_data() {
     // ARRANGE
     let app = spawn_app().await;
     let client = reqwest::Client::new();
+    // The confirmation email
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
 
     // ACT
     let response = client
-        .post(&format!("{}/subscription", app.root_address))
+        .post(format!("{}/subscription", app.root_address))
         .header("Content-Type", "application/x-www-form-urlencoded")
         .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
         .send()
         .await
         .expect("Failed to execute request.");
 
-    let saved = sqlx::query!("SELECT email, name FROM subscriptions;")
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions;")
         /*
          * What is the type of saved?
          * The query! macro returns an anonymous record type:
@@ -71,6 +86,8 @@ async fn subscribe_returns_200_ok_for_valid_form_data() {
     assert_eq!(200, response.status().as_u16());
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.name, "le guin");
+    // DOUBLE OPT-IN: nobody gets mailed until they click on the confirmation link
+    assert_eq!(saved.status, "pending_confirmation");
 }
 
 #[tokio::test]
@@ -105,7 +122,7 @@ async fn subscribe_returns_400_when_data_is_missing() {
     for (invalid_body, error_msg) in test_cases {
         // ACT
         let response = client
-            .post(&format!("{}/subscription", app.root_address))
+            .post(format!("{}/subscription", app.root_address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(invalid_body)
             .send()
@@ -123,8 +140,7 @@ async fn subscribe_returns_400_when_data_is_missing() {
     }
 }
 
-// No .await call, therefore no need for `spawn_app` to be async now.
-// We are also running tests, so it is not worth it to propagate errors:
+// We are running tests, so it is not worth it to propagate errors:
 // if we fail to perform the required setup we can just panic and crash.
 async fn spawn_app() -> TestApp {
     // WARNING: In order to achieve 'test isolation' & determinism
@@ -133,22 +149,31 @@ async fn spawn_app() -> TestApp {
     //  - run database migration
     let mut config: Settings = get_configuration().expect("Failed to read config");
     config.database.name = Uuid::new_v4().to_string();
+    // Any free port: the OS picks one for us
+    config.server.port = 0;
     let db_conn_pool = configure_database(&config.database).await;
 
-    let testing_address = config.server.with_random_port();
-    let listener: TcpListener =
-        TcpListener::bind(testing_address).expect("Failed to bind to the address");
+    // Whatever the configuration says, emails go to our mock server.
+    let email_server = MockServer::start().await;
+    config.email_client.transport = EmailTransportSettings::Http {
+        base_url: email_server.uri(),
+        authorization_token: SecretString::from("my-secret-token"),
+    };
+
+    let application = Application::build(config)
+        .await
+        .expect("Failed to build the application");
     // We retrieve the port assigned to us by the OS
-    let port = listener.local_addr().unwrap().port();
-    let server =
-        zero2prod::startup::run(listener, db_conn_pool.clone()).expect("Failed to bind address"); // Launch the server as a background task
+    let port = application.port();
+    // Launch the server as a background task
     // tokio::spawn returns a handle to the spawned future,
-    // but we have no use for it here, hence the non-binding let
-    let _ = tokio::spawn(server);
+    // but we have no use for it here, hence we explicitly drop it
+    drop(tokio::spawn(application.run_until_stopped()));
 
     TestApp {
         root_address: format!("http://127.0.0.1:{}", port),
-        db_conn_pool: db_conn_pool,
+        db_conn_pool,
+        email_server,
     }
 }
 
@@ -157,14 +182,14 @@ pub async fn configure_database(db_conf: &DatabaseSettings) -> PgPool {
         name: "postgres".to_string(),
         user: DBUser {
             name: "postgres".to_string(),
-            password: "password".to_string(),
+            password: SecretString::from("password"),
         },
         // CLAUDE: to comment ... i do understand we're 'copying' everything else form the
         // db_conf.clone()... but what's the proper term for what is done / this syntax ?
         ..db_conf.clone()
     };
 
-    let mut db_conn = PgConnection::connect(&maintenant_db_conf.connection_string())
+    let mut db_conn = PgConnection::connect_with(&maintenant_db_conf.with_db())
         .await
         .expect("Failed to connect to maintenance postgres instance");
 
@@ -174,9 +199,7 @@ pub async fn configure_database(db_conf: &DatabaseSettings) -> PgPool {
         .await
         .expect("Failed to create test db");
 
-    let db_conn_pool = PgPool::connect(&db_conf.clone().connection_string())
-        .await
-        .expect("Failed to create pool for test db");
+    let db_conn_pool = get_connection_pool(db_conf);
 
     sqlx::migrate!("./migrations")
         .run(&db_conn_pool)
@@ -0,0 +1,320 @@
+//! tests/smtp_transport.rs
+//! Driving the SMTP backend against a fake, in-process, SMTP server: no network needed.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use secrecy::SecretString;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_client::{EmailClient, SmtpAuthMechanism, SmtpTls, SmtpTransport};
+
+const USERNAME: &str = "newsletter";
+const PASSWORD: &str = "hunter2";
+
+// What the client is expected to put on the wire, base64-encoded (as per RFC 4954).
+// AUTH PLAIN: "\0newsletter\0hunter2"
+const PLAIN_CREDENTIALS: &str = "AG5ld3NsZXR0ZXIAaHVudGVyMg==";
+// AUTH LOGIN: "newsletter", then "hunter2"
+const LOGIN_USERNAME: &str = "bmV3c2xldHRlcg==";
+const LOGIN_PASSWORD: &str = "aHVudGVyMg==";
+
+/// What the fake server has seen so far.
+#[derive(Default)]
+struct Received {
+    connections: usize,
+    auth_mechanisms: Vec<String>,
+    messages: Vec<String>,
+}
+
+/// Just enough of RFC 5321 to have a conversation with lettre.
+struct FakeSmtpServer {
+    port: u16,
+    received: Arc<Mutex<Received>>,
+}
+
+impl FakeSmtpServer {
+    async fn start() -> Self {
+        Self::start_with(false).await
+    }
+
+    /// `reject_recipients`: answer `550` to every `RCPT TO`, like a relay refusing a mailbox.
+    async fn start_with(reject_recipients: bool) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the fake SMTP server");
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Received::default()));
+
+        let state = received.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                state.lock().unwrap().connections += 1;
+                tokio::spawn(handle_session(stream, state.clone(), reject_recipients));
+            }
+        });
+
+        Self { port, received }
+    }
+
+    fn connections(&self) -> usize {
+        self.received.lock().unwrap().connections
+    }
+
+    fn auth_mechanisms(&self) -> Vec<String> {
+        self.received.lock().unwrap().auth_mechanisms.clone()
+    }
+
+    fn messages(&self) -> Vec<String> {
+        self.received.lock().unwrap().messages.clone()
+    }
+}
+
+async fn handle_session(
+    stream: tokio::net::TcpStream,
+    received: Arc<Mutex<Received>>,
+    reject_recipients: bool,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let _ = writer.write_all(b"220 fake.smtp ESMTP ready\r\n").await;
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.to_ascii_uppercase();
+        let reply: String = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            "250-fake.smtp\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n".into()
+        } else if let Some(credentials) = line.strip_prefix("AUTH PLAIN ") {
+            received
+                .lock()
+                .unwrap()
+                .auth_mechanisms
+                .push("PLAIN".into());
+            auth_reply(credentials == PLAIN_CREDENTIALS)
+        } else if command == "AUTH LOGIN" {
+            received
+                .lock()
+                .unwrap()
+                .auth_mechanisms
+                .push("LOGIN".into());
+            // base64("Username:")
+            let _ = writer.write_all(b"334 VXNlcm5hbWU6\r\n").await;
+            let username = lines.next_line().await.ok().flatten().unwrap_or_default();
+            // base64("Password:")
+            let _ = writer.write_all(b"334 UGFzc3dvcmQ6\r\n").await;
+            let password = lines.next_line().await.ok().flatten().unwrap_or_default();
+            auth_reply(username == LOGIN_USERNAME && password == LOGIN_PASSWORD)
+        } else if command.starts_with("MAIL FROM") {
+            "250 2.1.0 OK\r\n".into()
+        } else if command.starts_with("RCPT TO") {
+            if reject_recipients {
+                "550 5.1.1 No such mailbox\r\n".into()
+            } else {
+                "250 2.1.5 OK\r\n".into()
+            }
+        } else if command == "DATA" {
+            let _ = writer
+                .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                .await;
+            let mut message = String::new();
+            while let Ok(Some(data_line)) = lines.next_line().await {
+                if data_line == "." {
+                    break;
+                }
+                message.push_str(&data_line);
+                message.push('\n');
+            }
+            received.lock().unwrap().messages.push(message);
+            "250 2.0.0 Queued\r\n".into()
+        } else if command == "QUIT" {
+            let _ = writer.write_all(b"221 2.0.0 Bye\r\n").await;
+            break;
+        } else {
+            // RSET, NOOP, ...
+            "250 2.0.0 OK\r\n".into()
+        };
+        if writer.write_all(reply.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn auth_reply(accepted: bool) -> String {
+    if accepted {
+        "235 2.7.0 Authentication successful\r\n".into()
+    } else {
+        "535 5.7.8 Authentication credentials invalid\r\n".into()
+    }
+}
+
+fn email(s: &str) -> SubscriberEmail {
+    SubscriberEmail::parse(s.into()).unwrap()
+}
+
+fn email_client(
+    server: &FakeSmtpServer,
+    credentials: Option<(String, SecretString)>,
+    auth_mechanism: SmtpAuthMechanism,
+) -> EmailClient {
+    let transport = SmtpTransport::new(
+        "127.0.0.1",
+        server.port,
+        // No certificates in tests: plaintext it is.
+        SmtpTls::None,
+        credentials,
+        auth_mechanism,
+        1,
+        Duration::from_secs(2),
+    )
+    .expect("Failed to build the SMTP transport");
+    EmailClient::new(email("newsletter@zero2prod.com"), transport)
+}
+
+fn credentials(password: &str) -> Option<(String, SecretString)> {
+    Some((USERNAME.into(), SecretString::from(password)))
+}
+
+#[tokio::test]
+async fn send_email_delivers_the_message_to_the_relay() {
+    // ARRANGE
+    let server = FakeSmtpServer::start().await;
+    let client = email_client(&server, None, SmtpAuthMechanism::Plain);
+
+    // ACT
+    let outcome = client
+        .send_email(
+            &email("ursula_le_guin@gmail.com"),
+            "Issue #1",
+            "<p>Hello!</p>",
+            "Hello!",
+        )
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_ok(), "{:?}", outcome);
+    let messages = server.messages();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].contains("To: ursula_le_guin@gmail.com"));
+    assert!(messages[0].contains("From: newsletter@zero2prod.com"));
+    assert!(messages[0].contains("Subject: Issue #1"));
+    // Both the plain-text and the HTML versions are there
+    assert!(messages[0].contains("text/plain"));
+    assert!(messages[0].contains("text/html"));
+}
+
+#[tokio::test]
+async fn send_email_authenticates_with_auth_plain() {
+    // ARRANGE
+    let server = FakeSmtpServer::start().await;
+    let client = email_client(&server, credentials(PASSWORD), SmtpAuthMechanism::Plain);
+
+    // ACT
+    let outcome = client
+        .send_email(&email("ursula_le_guin@gmail.com"), "s", "h", "t")
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_ok(), "{:?}", outcome);
+    assert_eq!(server.auth_mechanisms(), vec!["PLAIN".to_string()]);
+}
+
+#[tokio::test]
+async fn send_email_authenticates_with_auth_login() {
+    // ARRANGE
+    let server = FakeSmtpServer::start().await;
+    let client = email_client(&server, credentials(PASSWORD), SmtpAuthMechanism::Login);
+
+    // ACT
+    let outcome = client
+        .send_email(&email("ursula_le_guin@gmail.com"), "s", "h", "t")
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_ok(), "{:?}", outcome);
+    assert_eq!(server.auth_mechanisms(), vec!["LOGIN".to_string()]);
+}
+
+#[tokio::test]
+async fn send_email_fails_if_the_relay_rejects_the_credentials() {
+    // ARRANGE
+    let server = FakeSmtpServer::start().await;
+    let client = email_client(&server, credentials("wrong"), SmtpAuthMechanism::Plain);
+
+    // ACT
+    let outcome = client
+        .send_email(&email("ursula_le_guin@gmail.com"), "s", "h", "t")
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_err());
+    assert!(server.messages().is_empty());
+}
+
+#[tokio::test]
+async fn send_email_fails_if_the_relay_rejects_the_recipient() {
+    // ARRANGE
+    let server = FakeSmtpServer::start_with(true).await;
+    let client = email_client(&server, None, SmtpAuthMechanism::Plain);
+
+    // ACT
+    let outcome = client
+        .send_email(&email("ursula_le_guin@gmail.com"), "s", "h", "t")
+        .await;
+
+    // ASSERT
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn consecutive_emails_reuse_the_same_connection() {
+    // ARRANGE
+    let server = FakeSmtpServer::start().await;
+    let client = email_client(&server, credentials(PASSWORD), SmtpAuthMechanism::Plain);
+    let recipient = email("ursula_le_guin@gmail.com");
+
+    // ACT
+    for _ in 0..3 {
+        client
+            .send_email(&recipient, "s", "h", "t")
+            .await
+            .expect("Failed to send email");
+        // lettre hands the connection back to its pool on a background task:
+        // give it a moment, otherwise the next email would race it and open a new one.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // ASSERT
+    assert_eq!(server.messages().len(), 3);
+    assert_eq!(server.connections(), 1);
+    // Authenticated once, for the whole session
+    assert_eq!(server.auth_mechanisms().len(), 1);
+}
+
+#[tokio::test]
+async fn ping_succeeds_against_a_live_relay() {
+    // ARRANGE
+    let server = FakeSmtpServer::start().await;
+    let client = email_client(&server, credentials(PASSWORD), SmtpAuthMechanism::Plain);
+
+    // ACT
+    let outcome = client.ping().await;
+
+    // ASSERT
+    assert!(outcome.is_ok());
+    // Nothing was sent
+    assert!(server.messages().is_empty());
+}
+
+#[tokio::test]
+async fn ping_fails_if_the_relay_rejects_the_credentials() {
+    // ARRANGE
+    let server = FakeSmtpServer::start().await;
+    let client = email_client(&server, credentials("wrong"), SmtpAuthMechanism::Plain);
+
+    // ACT
+    let outcome = client.ping().await;
+
+    // ASSERT
+    assert!(outcome.is_err());
+}
@@ -0,0 +1,234 @@
+//! tests/telemetry.rs
+//! Where the logs go, and what they look like.
+
+use std::sync::{Arc, Mutex};
+
+use secrecy::SecretString;
+use zero2prod::configuration::{RedactionMode, RedactionSettings};
+use zero2prod::telemetry::{Redaction, get_subscriber};
+
+/// A sink that keeps everything written to it, for tests to read back.
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CapturedLogs {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+
+    /// One JSON object per record.
+    fn records(&self) -> Vec<serde_json::Value> {
+        self.text()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Not a JSON record"))
+            .collect()
+    }
+}
+
+#[test]
+fn records_go_to_the_given_sink_as_bunyan_json() {
+    // ARRANGE
+    let logs = CapturedLogs::default();
+    let sink = logs.clone();
+    // Any `Fn() -> impl Write` is a `MakeWriter`
+    let subscriber = get_subscriber(
+        "test".into(),
+        "info".into(),
+        move || sink.clone(),
+        Redaction::Off,
+        None,
+    );
+
+    // ACT
+    // Scoped to this thread, rather than global: tests run side by side
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("HTTP request", request_id = "abc");
+        let _guard = span.enter();
+        tracing::info!("Hello from a test");
+    });
+
+    // ASSERT
+    let records = logs.records();
+    let event = records
+        .iter()
+        // e.g. "[HTTP REQUEST - EVENT] Hello from a test": prefixed with the span's name
+        .find(|record| {
+            record["msg"]
+                .as_str()
+                .unwrap()
+                .ends_with("Hello from a test")
+        })
+        .unwrap_or_else(|| panic!("The event was not logged: {:?}", records));
+    assert_eq!(event["name"], "test");
+    // Span fields are carried over to the events within it
+    assert_eq!(event["request_id"], "abc");
+}
+
+#[test]
+fn nothing_is_written_to_a_sink_that_discards_everything() {
+    // ARRANGE
+    let subscriber = get_subscriber(
+        "test".into(),
+        "info".into(),
+        std::io::sink,
+        Redaction::Off,
+        None,
+    );
+
+    // ACT & ASSERT: no panic, whether `RUST_LOG` is set or not
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("Into the void");
+    });
+}
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+/// What `subscribe` logs, more or less: PII on the span, and on an event of its own.
+fn log_a_subscription(redaction: Redaction) -> CapturedLogs {
+    let logs = CapturedLogs::default();
+    let sink = logs.clone();
+    let subscriber = get_subscriber(
+        "test".into(),
+        "info".into(),
+        move || sink.clone(),
+        redaction,
+        None,
+    );
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!(
+            "Adding a new subscriber",
+            subscriber_email = EMAIL,
+            subscriber_name = "le guin"
+        );
+        let _guard = span.enter();
+        tracing::info!("New subscriber details saved");
+        tracing::warn!(subscriber_email = EMAIL, "Skipping a subscriber");
+    });
+    logs
+}
+
+fn configured(mode: RedactionMode, key: Option<&str>) -> Result<Redaction, anyhow::Error> {
+    Redaction::from_settings(&RedactionSettings {
+        mode,
+        key: key.map(SecretString::from),
+    })
+}
+
+#[test]
+fn hashed_pii_never_reaches_the_logs_but_can_still_be_correlated() {
+    // ARRANGE
+    let key = "k".repeat(32);
+    let redaction = configured(RedactionMode::Hash, Some(&key)).unwrap();
+
+    // ACT
+    let logs = log_a_subscription(redaction);
+
+    // ASSERT
+    let text = logs.text();
+    assert!(!text.contains(EMAIL), "{}", text);
+    assert!(!text.contains("le guin"), "{}", text);
+    let records = logs.records();
+    // Span start and end, both events: same email, same hash
+    let hashes: Vec<&str> = records
+        .iter()
+        .map(|record| record["subscriber_email"].as_str().unwrap())
+        .collect();
+    assert_eq!(hashes.len(), 4, "{:?}", records);
+    assert!(hashes[0].starts_with("hmac:"));
+    assert!(hashes.iter().all(|hash| *hash == hashes[0]), "{:?}", hashes);
+    // Keyed: without the key, there is no telling whose hash it is
+    let other_key = "o".repeat(32);
+    let other = configured(RedactionMode::Hash, Some(&other_key)).unwrap();
+    assert_ne!(other.apply(EMAIL), hashes[0]);
+}
+
+#[test]
+fn pii_is_masked_or_left_alone_as_configured() {
+    // ACT
+    let masked = log_a_subscription(configured(RedactionMode::Mask, None).unwrap());
+    let as_is = log_a_subscription(configured(RedactionMode::Off, None).unwrap());
+
+    // ASSERT
+    assert!(!masked.text().contains(EMAIL));
+    assert!(
+        masked
+            .records()
+            .iter()
+            .all(|record| record["subscriber_email"] == "[REDACTED]")
+    );
+    assert!(
+        as_is
+            .records()
+            .iter()
+            .all(|record| record["subscriber_email"] == EMAIL)
+    );
+}
+
+/// What a failed request logs, more or less: an error quoting the recipient's address.
+fn log_a_rejected_recipient(redaction: Redaction) -> serde_json::Value {
+    let logs = CapturedLogs::default();
+    let sink = logs.clone();
+    let subscriber = get_subscriber(
+        "test".into(),
+        "info".into(),
+        move || sink.clone(),
+        redaction,
+        None,
+    );
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!(
+            subscriber_email = EMAIL,
+            error.cause_chain = "permanent error (550): 5.1.1 <ursula_le_guin@gmail.com>: \
+                Recipient address rejected. Please write to postmaster@gmail.com.",
+            "Request failed"
+        );
+    });
+    logs.records().pop().unwrap()
+}
+
+#[test]
+fn addresses_quoted_in_errors_are_redacted_like_the_pii_fields() {
+    // ARRANGE
+    let key = "k".repeat(32);
+    let redaction = configured(RedactionMode::Hash, Some(&key)).unwrap();
+
+    // ACT
+    let hashed = log_a_rejected_recipient(redaction);
+    let masked = log_a_rejected_recipient(configured(RedactionMode::Mask, None).unwrap());
+
+    // ASSERT
+    let cause_chain = hashed["error.cause_chain"].as_str().unwrap();
+    // Only the addresses: the rest of the message is worth keeping
+    assert!(cause_chain.starts_with("permanent error (550): 5.1.1 <hmac:"));
+    assert!(cause_chain.contains(">: Recipient address rejected."));
+    assert!(!cause_chain.contains("@gmail.com"), "{}", cause_chain);
+    // Same address, same hash, whether quoted or not
+    let hash = hashed["subscriber_email"].as_str().unwrap();
+    assert!(
+        cause_chain.contains(&format!("<{}>", hash)),
+        "{}",
+        cause_chain
+    );
+    assert_eq!(
+        masked["error.cause_chain"],
+        "permanent error (550): 5.1.1 <[REDACTED]>: Recipient address rejected. \
+        Please write to [REDACTED]."
+    );
+}
+
+#[test]
+fn hashing_requires_a_long_enough_key() {
+    assert!(configured(RedactionMode::Hash, None).is_err());
+    assert!(configured(RedactionMode::Hash, Some("too short")).is_err());
+}